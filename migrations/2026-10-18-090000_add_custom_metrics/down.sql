@@ -0,0 +1 @@
+DROP TABLE custom_metrics;
\ No newline at end of file
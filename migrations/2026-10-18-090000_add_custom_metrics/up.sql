@@ -0,0 +1,13 @@
+CREATE TABLE custom_metrics (
+	id BIGSERIAL,
+	name VARCHAR(128) NOT NULL,
+	labels JSONB NOT NULL DEFAULT '{}',
+	value FLOAT NOT NULL,
+	host_uuid VARCHAR(48) NOT NULL,
+	created_at TIMESTAMP NOT NULL
+);
+
+SELECT create_hypertable('custom_metrics', 'created_at', chunk_time_interval => INTERVAL '1 day');
+SELECT add_retention_policy('custom_metrics', INTERVAL '10 days');
+
+CREATE INDEX custom_metrics_idx_created_at ON custom_metrics(host_uuid, name, created_at DESC);
@@ -0,0 +1 @@
+ALTER TABLE hosts DROP COLUMN clock_skew;
\ No newline at end of file
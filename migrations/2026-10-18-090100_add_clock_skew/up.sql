@@ -0,0 +1 @@
+ALTER TABLE hosts ADD COLUMN clock_skew BIGINT;
\ No newline at end of file
@@ -0,0 +1,7 @@
+SELECT remove_continuous_aggregate_policy('custom_metrics_10m');
+
+SELECT remove_continuous_aggregate_policy('custom_metrics_30m');
+
+DROP MATERIALIZED VIEW IF EXISTS custom_metrics_10m;
+
+DROP MATERIALIZED VIEW IF EXISTS custom_metrics_30m;
@@ -0,0 +1,35 @@
+CREATE MATERIALIZED VIEW IF NOT EXISTS custom_metrics_10m WITH (timescaledb.continuous)
+    AS SELECT
+		host_uuid,
+		name,
+		labels,
+		time_bucket('10m', created_at) as time,
+		avg(value)::float8 as value
+	FROM custom_metrics
+    GROUP BY host_uuid, name, labels, time
+	WITH NO DATA;
+
+SELECT add_retention_policy('custom_metrics_10m', INTERVAL '4 days');
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS custom_metrics_30m WITH (timescaledb.continuous)
+    AS SELECT
+		host_uuid,
+		name,
+		labels,
+		time_bucket('30m', created_at) as time,
+		avg(value)::float8 as value
+	FROM custom_metrics
+    GROUP BY host_uuid, name, labels, time
+	WITH NO DATA;
+
+SELECT add_retention_policy('custom_metrics_30m', INTERVAL '1 month');
+
+SELECT add_continuous_aggregate_policy('custom_metrics_10m',
+  start_offset => INTERVAL '3 days',
+  end_offset => INTERVAL '10 minutes',
+  schedule_interval => INTERVAL '10 minutes');
+
+SELECT add_continuous_aggregate_policy('custom_metrics_30m',
+  start_offset => INTERVAL '3 days',
+  end_offset => INTERVAL '30 minutes',
+  schedule_interval => INTERVAL '30 minutes');
@@ -0,0 +1 @@
+DROP TABLE processes;
\ No newline at end of file
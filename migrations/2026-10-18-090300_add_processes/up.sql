@@ -0,0 +1,17 @@
+CREATE TABLE processes (
+	id BIGSERIAL,
+	pid INT NOT NULL,
+	name VARCHAR(128) NOT NULL,
+	cmdline_hash VARCHAR(64) NOT NULL,
+	cpu FLOAT NOT NULL,
+	rss BIGINT NOT NULL,
+	read_bytes BIGINT NOT NULL,
+	write_bytes BIGINT NOT NULL,
+	host_uuid VARCHAR(48) NOT NULL,
+	created_at TIMESTAMP NOT NULL
+);
+
+SELECT create_hypertable('processes', 'created_at', chunk_time_interval => INTERVAL '1 day');
+SELECT add_retention_policy('processes', INTERVAL '10 days');
+
+CREATE INDEX processes_idx_created_at ON processes(host_uuid, created_at DESC);
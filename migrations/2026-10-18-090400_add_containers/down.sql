@@ -0,0 +1,5 @@
+DROP MATERIALIZED VIEW IF EXISTS containers_10m;
+
+DROP MATERIALIZED VIEW IF EXISTS containers_30m;
+
+DROP TABLE containers;
\ No newline at end of file
@@ -0,0 +1,67 @@
+CREATE TABLE containers (
+	id BIGSERIAL,
+	container_id VARCHAR(128) NOT NULL,
+	name VARCHAR(128) NOT NULL,
+	cpu_usage BIGINT NOT NULL,
+	memory_usage BIGINT NOT NULL,
+	memory_limit BIGINT NOT NULL,
+	read_bytes BIGINT NOT NULL,
+	write_bytes BIGINT NOT NULL,
+	rx_bytes BIGINT NOT NULL,
+	tx_bytes BIGINT NOT NULL,
+	host_uuid VARCHAR(48) NOT NULL,
+	created_at TIMESTAMP NOT NULL
+);
+
+SELECT create_hypertable('containers', 'created_at', chunk_time_interval => INTERVAL '1 day');
+SELECT add_retention_policy('containers', INTERVAL '10 days');
+
+CREATE INDEX containers_idx_created_at ON containers(host_uuid, created_at DESC);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS containers_10m WITH (timescaledb.continuous)
+    AS SELECT
+		host_uuid,
+		time_bucket('10m', created_at) as time,
+		container_id,
+		name,
+		avg(cpu_usage)::int8 as cpu_usage,
+		avg(memory_usage)::int8 as memory_usage,
+		avg(memory_limit)::int8 as memory_limit,
+		avg(read_bytes)::int8 as read_bytes,
+		avg(write_bytes)::int8 as write_bytes,
+		avg(rx_bytes)::int8 as rx_bytes,
+		avg(tx_bytes)::int8 as tx_bytes
+	FROM containers
+    GROUP BY host_uuid, time, container_id, name
+	WITH NO DATA;
+
+SELECT add_retention_policy('containers_10m', INTERVAL '4 days');
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS containers_30m WITH (timescaledb.continuous)
+    AS SELECT
+		host_uuid,
+		time_bucket('30m', created_at) as time,
+		container_id,
+		name,
+		avg(cpu_usage)::int8 as cpu_usage,
+		avg(memory_usage)::int8 as memory_usage,
+		avg(memory_limit)::int8 as memory_limit,
+		avg(read_bytes)::int8 as read_bytes,
+		avg(write_bytes)::int8 as write_bytes,
+		avg(rx_bytes)::int8 as rx_bytes,
+		avg(tx_bytes)::int8 as tx_bytes
+	FROM containers
+    GROUP BY host_uuid, time, container_id, name
+	WITH NO DATA;
+
+SELECT add_retention_policy('containers_30m', INTERVAL '1 month');
+
+SELECT add_continuous_aggregate_policy('containers_10m',
+  start_offset => INTERVAL '3 days',
+  end_offset => INTERVAL '10 minutes',
+  schedule_interval => INTERVAL '10 minutes');
+
+SELECT add_continuous_aggregate_policy('containers_30m',
+  start_offset => INTERVAL '3 days',
+  end_offset => INTERVAL '30 minutes',
+  schedule_interval => INTERVAL '30 minutes');
\ No newline at end of file
@@ -0,0 +1,15 @@
+-- sensors
+
+DROP MATERIALIZED VIEW IF EXISTS sensors_10m;
+
+DROP MATERIALIZED VIEW IF EXISTS sensors_30m;
+
+DROP TABLE sensors;
+
+-- smart
+
+DROP MATERIALIZED VIEW IF EXISTS smart_10m;
+
+DROP MATERIALIZED VIEW IF EXISTS smart_30m;
+
+DROP TABLE smart;
\ No newline at end of file
@@ -0,0 +1,115 @@
+-- sensors
+
+CREATE TABLE sensors (
+	id BIGSERIAL,
+	label VARCHAR(128) NOT NULL,
+	kind VARCHAR(16) NOT NULL,
+	value FLOAT NOT NULL,
+	crit FLOAT,
+	host_uuid VARCHAR(48) NOT NULL,
+	created_at TIMESTAMP NOT NULL
+);
+
+SELECT create_hypertable('sensors', 'created_at', chunk_time_interval => INTERVAL '1 day');
+SELECT add_retention_policy('sensors', INTERVAL '10 days');
+
+CREATE INDEX sensors_idx_created_at ON sensors(host_uuid, created_at DESC);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS sensors_10m WITH (timescaledb.continuous)
+    AS SELECT
+		host_uuid,
+		time_bucket('10m', created_at) as time,
+		label,
+		kind,
+		avg(value)::float8 as value,
+		max(crit)::float8 as crit
+	FROM sensors
+    GROUP BY host_uuid, time, label, kind
+	WITH NO DATA;
+
+SELECT add_retention_policy('sensors_10m', INTERVAL '4 days');
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS sensors_30m WITH (timescaledb.continuous)
+    AS SELECT
+		host_uuid,
+		time_bucket('30m', created_at) as time,
+		label,
+		kind,
+		avg(value)::float8 as value,
+		max(crit)::float8 as crit
+	FROM sensors
+    GROUP BY host_uuid, time, label, kind
+	WITH NO DATA;
+
+SELECT add_retention_policy('sensors_30m', INTERVAL '1 month');
+
+SELECT add_continuous_aggregate_policy('sensors_10m',
+  start_offset => INTERVAL '3 days',
+  end_offset => INTERVAL '10 minutes',
+  schedule_interval => INTERVAL '10 minutes');
+
+SELECT add_continuous_aggregate_policy('sensors_30m',
+  start_offset => INTERVAL '3 days',
+  end_offset => INTERVAL '30 minutes',
+  schedule_interval => INTERVAL '30 minutes');
+
+-- smart
+
+CREATE TABLE smart (
+	id BIGSERIAL,
+	disk_name VARCHAR(128) NOT NULL,
+	reallocated_sectors BIGINT NOT NULL,
+	pending_sectors BIGINT NOT NULL,
+	wear_level BIGINT,
+	power_on_hours BIGINT NOT NULL,
+	failing INT NOT NULL,
+	host_uuid VARCHAR(48) NOT NULL,
+	created_at TIMESTAMP NOT NULL
+);
+
+SELECT create_hypertable('smart', 'created_at', chunk_time_interval => INTERVAL '1 day');
+SELECT add_retention_policy('smart', INTERVAL '10 days');
+
+CREATE INDEX smart_idx_created_at ON smart(host_uuid, created_at DESC);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS smart_10m WITH (timescaledb.continuous)
+    AS SELECT
+		host_uuid,
+		time_bucket('10m', created_at) as time,
+		disk_name,
+		max(reallocated_sectors)::int8 as reallocated_sectors,
+		max(pending_sectors)::int8 as pending_sectors,
+		max(wear_level)::int8 as wear_level,
+		max(power_on_hours)::int8 as power_on_hours,
+		max(failing)::int4 as failing
+	FROM smart
+    GROUP BY host_uuid, time, disk_name
+	WITH NO DATA;
+
+SELECT add_retention_policy('smart_10m', INTERVAL '4 days');
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS smart_30m WITH (timescaledb.continuous)
+    AS SELECT
+		host_uuid,
+		time_bucket('30m', created_at) as time,
+		disk_name,
+		max(reallocated_sectors)::int8 as reallocated_sectors,
+		max(pending_sectors)::int8 as pending_sectors,
+		max(wear_level)::int8 as wear_level,
+		max(power_on_hours)::int8 as power_on_hours,
+		max(failing)::int4 as failing
+	FROM smart
+    GROUP BY host_uuid, time, disk_name
+	WITH NO DATA;
+
+SELECT add_retention_policy('smart_30m', INTERVAL '1 month');
+
+SELECT add_continuous_aggregate_policy('smart_10m',
+  start_offset => INTERVAL '3 days',
+  end_offset => INTERVAL '10 minutes',
+  schedule_interval => INTERVAL '10 minutes');
+
+SELECT add_continuous_aggregate_policy('smart_30m',
+  start_offset => INTERVAL '3 days',
+  end_offset => INTERVAL '30 minutes',
+  schedule_interval => INTERVAL '30 minutes');
\ No newline at end of file
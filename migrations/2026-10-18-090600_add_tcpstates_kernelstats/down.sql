@@ -0,0 +1,15 @@
+-- tcpstates
+
+DROP MATERIALIZED VIEW IF EXISTS tcpstates_10m;
+
+DROP MATERIALIZED VIEW IF EXISTS tcpstates_30m;
+
+DROP TABLE tcpstates;
+
+-- kernelstats
+
+DROP MATERIALIZED VIEW IF EXISTS kernelstats_10m;
+
+DROP MATERIALIZED VIEW IF EXISTS kernelstats_30m;
+
+DROP TABLE kernelstats;
\ No newline at end of file
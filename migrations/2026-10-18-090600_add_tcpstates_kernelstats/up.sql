@@ -0,0 +1,133 @@
+-- tcpstates
+
+CREATE TABLE tcpstates (
+	id BIGSERIAL,
+	established BIGINT NOT NULL,
+	syn_sent BIGINT NOT NULL,
+	syn_recv BIGINT NOT NULL,
+	fin_wait1 BIGINT NOT NULL,
+	fin_wait2 BIGINT NOT NULL,
+	time_wait BIGINT NOT NULL,
+	close BIGINT NOT NULL,
+	close_wait BIGINT NOT NULL,
+	last_ack BIGINT NOT NULL,
+	listen BIGINT NOT NULL,
+	closing BIGINT NOT NULL,
+	host_uuid VARCHAR(48) NOT NULL,
+	created_at TIMESTAMP NOT NULL
+);
+
+SELECT create_hypertable('tcpstates', 'created_at', chunk_time_interval => INTERVAL '1 day');
+SELECT add_retention_policy('tcpstates', INTERVAL '10 days');
+
+CREATE INDEX tcpstates_idx_created_at ON tcpstates(host_uuid, created_at DESC);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS tcpstates_10m WITH (timescaledb.continuous)
+    AS SELECT
+		host_uuid,
+		time_bucket('10m', created_at) as time,
+		avg(established)::int8 as established,
+		avg(syn_sent)::int8 as syn_sent,
+		avg(syn_recv)::int8 as syn_recv,
+		avg(fin_wait1)::int8 as fin_wait1,
+		avg(fin_wait2)::int8 as fin_wait2,
+		avg(time_wait)::int8 as time_wait,
+		avg(close)::int8 as close,
+		avg(close_wait)::int8 as close_wait,
+		avg(last_ack)::int8 as last_ack,
+		avg(listen)::int8 as listen,
+		avg(closing)::int8 as closing
+	FROM tcpstates
+    GROUP BY host_uuid, time
+	WITH NO DATA;
+
+SELECT add_retention_policy('tcpstates_10m', INTERVAL '4 days');
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS tcpstates_30m WITH (timescaledb.continuous)
+    AS SELECT
+		host_uuid,
+		time_bucket('30m', created_at) as time,
+		avg(established)::int8 as established,
+		avg(syn_sent)::int8 as syn_sent,
+		avg(syn_recv)::int8 as syn_recv,
+		avg(fin_wait1)::int8 as fin_wait1,
+		avg(fin_wait2)::int8 as fin_wait2,
+		avg(time_wait)::int8 as time_wait,
+		avg(close)::int8 as close,
+		avg(close_wait)::int8 as close_wait,
+		avg(last_ack)::int8 as last_ack,
+		avg(listen)::int8 as listen,
+		avg(closing)::int8 as closing
+	FROM tcpstates
+    GROUP BY host_uuid, time
+	WITH NO DATA;
+
+SELECT add_retention_policy('tcpstates_30m', INTERVAL '1 month');
+
+SELECT add_continuous_aggregate_policy('tcpstates_10m',
+  start_offset => INTERVAL '3 days',
+  end_offset => INTERVAL '10 minutes',
+  schedule_interval => INTERVAL '10 minutes');
+
+SELECT add_continuous_aggregate_policy('tcpstates_30m',
+  start_offset => INTERVAL '3 days',
+  end_offset => INTERVAL '30 minutes',
+  schedule_interval => INTERVAL '30 minutes');
+
+-- kernelstats
+
+CREATE TABLE kernelstats (
+	id BIGSERIAL,
+	open_fds BIGINT NOT NULL,
+	max_fds BIGINT NOT NULL,
+	conntrack_count BIGINT NOT NULL,
+	conntrack_max BIGINT NOT NULL,
+	entropy BIGINT NOT NULL,
+	host_uuid VARCHAR(48) NOT NULL,
+	created_at TIMESTAMP NOT NULL
+);
+
+SELECT create_hypertable('kernelstats', 'created_at', chunk_time_interval => INTERVAL '1 day');
+SELECT add_retention_policy('kernelstats', INTERVAL '10 days');
+
+CREATE INDEX kernelstats_idx_created_at ON kernelstats(host_uuid, created_at DESC);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS kernelstats_10m WITH (timescaledb.continuous)
+    AS SELECT
+		host_uuid,
+		time_bucket('10m', created_at) as time,
+		avg(open_fds)::int8 as open_fds,
+		avg(max_fds)::int8 as max_fds,
+		avg(conntrack_count)::int8 as conntrack_count,
+		avg(conntrack_max)::int8 as conntrack_max,
+		avg(entropy)::int8 as entropy
+	FROM kernelstats
+    GROUP BY host_uuid, time
+	WITH NO DATA;
+
+SELECT add_retention_policy('kernelstats_10m', INTERVAL '4 days');
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS kernelstats_30m WITH (timescaledb.continuous)
+    AS SELECT
+		host_uuid,
+		time_bucket('30m', created_at) as time,
+		avg(open_fds)::int8 as open_fds,
+		avg(max_fds)::int8 as max_fds,
+		avg(conntrack_count)::int8 as conntrack_count,
+		avg(conntrack_max)::int8 as conntrack_max,
+		avg(entropy)::int8 as entropy
+	FROM kernelstats
+    GROUP BY host_uuid, time
+	WITH NO DATA;
+
+SELECT add_retention_policy('kernelstats_30m', INTERVAL '1 month');
+
+SELECT add_continuous_aggregate_policy('kernelstats_10m',
+  start_offset => INTERVAL '3 days',
+  end_offset => INTERVAL '10 minutes',
+  schedule_interval => INTERVAL '10 minutes');
+
+SELECT add_continuous_aggregate_policy('kernelstats_30m',
+  start_offset => INTERVAL '3 days',
+  end_offset => INTERVAL '30 minutes',
+  schedule_interval => INTERVAL '30 minutes');
\ No newline at end of file
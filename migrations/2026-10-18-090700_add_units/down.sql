@@ -0,0 +1,3 @@
+DROP TABLE unit_transitions;
+
+DROP TABLE units;
\ No newline at end of file
@@ -0,0 +1,27 @@
+-- Current state of the systemd units reported by the hosts,
+-- created_at being the time of the last report.
+CREATE TABLE units (
+	host_uuid VARCHAR(48) NOT NULL,
+	unit VARCHAR(128) NOT NULL,
+	state VARCHAR(16) NOT NULL,
+	failed INT NOT NULL,
+	restarts BIGINT NOT NULL,
+	changed_at TIMESTAMP NOT NULL,
+	created_at TIMESTAMP NOT NULL,
+	PRIMARY KEY (host_uuid, unit)
+);
+
+CREATE TABLE unit_transitions (
+	id BIGSERIAL,
+	unit VARCHAR(128) NOT NULL,
+	from_state VARCHAR(16),
+	to_state VARCHAR(16) NOT NULL,
+	restarts BIGINT NOT NULL,
+	host_uuid VARCHAR(48) NOT NULL,
+	created_at TIMESTAMP NOT NULL
+);
+
+SELECT create_hypertable('unit_transitions', 'created_at', chunk_time_interval => INTERVAL '7 days');
+SELECT add_retention_policy('unit_transitions', INTERVAL '1 month');
+
+CREATE INDEX unit_transitions_idx_created_at ON unit_transitions(host_uuid, created_at DESC);
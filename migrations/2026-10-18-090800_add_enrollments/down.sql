@@ -0,0 +1 @@
+DROP TABLE enrollments;
\ No newline at end of file
@@ -0,0 +1,12 @@
+-- Audit of the api keys bound to a host by the server (auto_enroll)
+CREATE TABLE enrollments (
+	id BIGSERIAL PRIMARY KEY,
+	host_uuid VARCHAR(48) NOT NULL,
+	key_id BIGINT NOT NULL,
+	customer_id UUID NOT NULL,
+	berta VARCHAR(64) NOT NULL,
+	remote_addr VARCHAR(64),
+	created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX enrollments_idx_host_uuid ON enrollments(host_uuid);
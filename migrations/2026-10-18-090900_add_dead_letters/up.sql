@@ -0,0 +1,18 @@
+-- Payloads rejected by the ingest (or which couldn't be written),
+-- bounded to the dead_letters_max most recent entries.
+-- Payloads rejected by SptkValidator are unverified: host_uuid is NULL and
+-- claimed_uuid is the uuid of the query, they are bounded separately.
+CREATE TABLE dead_letters (
+	id BIGSERIAL PRIMARY KEY,
+	host_uuid VARCHAR(48),
+	claimed_uuid VARCHAR(48),
+	route VARCHAR(128) NOT NULL,
+	reason TEXT NOT NULL,
+	headers JSONB NOT NULL DEFAULT '{}',
+	body BYTEA NOT NULL,
+	replayed_at TIMESTAMP,
+	created_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX dead_letters_idx_host_uuid ON dead_letters(host_uuid, id DESC);
+CREATE INDEX dead_letters_idx_claimed_uuid ON dead_letters(claimed_uuid, id DESC);
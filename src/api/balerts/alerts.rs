@@ -15,7 +15,7 @@ use std::hash::{Hash, Hasher};
 use crate::api::{SpecificAlert, SpecificPaged};
 use crate::{field_changed_is_same, field_changed_is_same_opt, ALERTSHASH_CACHE};
 
-use super::AlertsUpdate;
+use super::{AlertsUpdate, ALERT_TABLES};
 
 /// GET /api/alerts
 /// Return all alerts
@@ -152,6 +152,13 @@ pub async fn alerts_test(
         _ => (),
     };
 
+    if !ALERT_TABLES.contains(&item.table.as_str()) {
+        return Err(ApiError::InvalidRequestError(Some(format!(
+            "alerts can only be defined on the tables {:?}",
+            ALERT_TABLES
+        ))));
+    }
+
     // Compute the Hash of the Alert
     let mut hasher = AHasher::default();
     item.hash(&mut hasher);
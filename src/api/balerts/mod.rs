@@ -18,6 +18,27 @@ use sproot::models::{AlertsDTO, AlertsDTOUpdate};
 pub mod alerts;
 pub mod incidents;
 
+/// Tables an alert can be defined on, all of them have
+/// the host_uuid and created_at columns the query relies on.
+/// The state of a systemd unit is exposed through the numeric
+/// units.failed column (lookup on failed, where unit = 'xyz').
+pub const ALERT_TABLES: [&str; 14] = [
+    "cpustats",
+    "cputimes",
+    "disks",
+    "ioblocks",
+    "ionets",
+    "loadavg",
+    "memory",
+    "swap",
+    "containers",
+    "sensors",
+    "smart",
+    "tcpstates",
+    "kernelstats",
+    "units",
+];
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct AlertsUpdate {
     whole: AlertsDTO,
@@ -0,0 +1,56 @@
+use actix_web::{web, HttpResponse};
+use sproot::apierrors::ApiError;
+use sproot::models::MetricsPool;
+
+use crate::models::{downsample, Container, ContainerSeen};
+
+use super::{ContainerDated, Dated, SpecificDated, GRANULARITY_HEADER};
+
+/// GET /api/containers
+/// Return the containers metrics (optionally of a single container) of a host
+pub async fn containers(
+    metrics: web::Data<MetricsPool>,
+    info: web::Query<ContainerDated>,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route GET /api/containers : {:?}", info);
+
+    let granularity = info.get_granularity();
+    let max_points = info.get_max_points()?;
+    let data = web::block(move || {
+        let data = Container::get_dated(
+            &mut metrics.pool.get()?,
+            &info.uuid,
+            info.container_id.as_deref(),
+            granularity,
+            info.min_date,
+            info.max_date,
+        )?;
+        Ok::<_, ApiError>(downsample(data, max_points))
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((GRANULARITY_HEADER, granularity.as_str()))
+        .json(data))
+}
+
+/// GET /api/containers/list
+/// Return the containers seen on a host during a time range
+pub async fn containers_list(
+    metrics: web::Data<MetricsPool>,
+    info: web::Query<SpecificDated>,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route GET /api/containers/list : {:?}", info);
+
+    let data = web::block(move || {
+        ContainerSeen::get_dated(
+            &mut metrics.pool.get()?,
+            &info.uuid,
+            info.min_date,
+            info.max_date,
+        )
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(data))
+}
@@ -4,25 +4,32 @@ use sproot::models::BaseMetrics;
 use sproot::models::CpuStats;
 use sproot::models::MetricsPool;
 
-use super::SpecificDated;
+use crate::models::{CounterRows, CpuStatsPoint};
+
+use super::{dated_response, CounterDated};
 
 /// GET /api/cpustats
 /// Return cpustats for a particular host
 pub async fn cpustats(
     metrics: web::Data<MetricsPool>,
-    info: web::Query<SpecificDated>,
+    info: web::Query<CounterDated>,
 ) -> Result<HttpResponse, ApiError> {
     trace!("Route GET /api/cpustats : {:?}", info);
 
-    let data = web::block(move || {
-        CpuStats::get_dated(
-            &mut metrics.pool.get()?,
-            &info.uuid,
-            info.min_date,
-            info.max_date,
-        )
-    })
-    .await??;
-
-    Ok(HttpResponse::Ok().json(data))
+    dated_response(
+        metrics,
+        info.into_inner(),
+        CpuStats::get_dated,
+        |conn, info, granularity, max_points| {
+            let data = CpuStatsPoint::get_dated(
+                conn,
+                &info.uuid,
+                granularity,
+                info.min_date,
+                info.max_date,
+            )?;
+            Ok(CounterRows::new(data, info.rate, max_points))
+        },
+    )
+    .await
 }
@@ -4,25 +4,32 @@ use sproot::models::BaseMetrics;
 use sproot::models::CpuTimes;
 use sproot::models::MetricsPool;
 
-use super::SpecificDated;
+use crate::models::{CounterRows, CpuTimesPoint};
+
+use super::{dated_response, CounterDated};
 
 /// GET /api/cputimes
 /// Return cputimes for a particular host
 pub async fn cputimes(
     metrics: web::Data<MetricsPool>,
-    info: web::Query<SpecificDated>,
+    info: web::Query<CounterDated>,
 ) -> Result<HttpResponse, ApiError> {
     trace!("Route GET /api/cputimes : {:?}", info);
 
-    let data = web::block(move || {
-        CpuTimes::get_dated(
-            &mut metrics.pool.get()?,
-            &info.uuid,
-            info.min_date,
-            info.max_date,
-        )
-    })
-    .await??;
-
-    Ok(HttpResponse::Ok().json(data))
+    dated_response(
+        metrics,
+        info.into_inner(),
+        CpuTimes::get_dated,
+        |conn, info, granularity, max_points| {
+            let data = CpuTimesPoint::get_dated(
+                conn,
+                &info.uuid,
+                granularity,
+                info.min_date,
+                info.max_date,
+            )?;
+            Ok(CounterRows::new(data, info.rate, max_points))
+        },
+    )
+    .await
 }
@@ -0,0 +1,147 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sproot::apierrors::ApiError;
+use sproot::models::{MetricsPool, Specific};
+use std::collections::BTreeMap;
+
+use crate::{
+    ingest::{busy, check_future, valid_metric_name, RejectedSample, PIPELINE},
+    models::{
+        downsample, CustomMetric, CustomMetricName, NewCustomMetric, Rows, RAW_RETENTION_DAYS,
+    },
+    utils::payload,
+};
+
+use super::{CustomDated, Dated, SpecificDated, GRANULARITY_HEADER};
+
+#[derive(Debug, Deserialize)]
+pub struct CustomSample {
+    pub name: String,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+    /// Default to the time of the request
+    pub created_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CustomReport {
+    pub accepted: usize,
+    pub rejected: Vec<RejectedSample>,
+}
+
+/// GET /api/custom
+/// Return the custom metrics (optionally filtered by name) of a host
+pub async fn custom(
+    metrics: web::Data<MetricsPool>,
+    info: web::Query<CustomDated>,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route GET /api/custom : {:?}", info);
+
+    let granularity = info.get_granularity();
+    let max_points = info.get_max_points()?;
+    let data = web::block(move || {
+        let data = CustomMetric::get_dated(
+            &mut metrics.pool.get()?,
+            &info.uuid,
+            info.name.as_deref(),
+            granularity,
+            info.min_date,
+            info.max_date,
+        )?;
+        Ok::<_, ApiError>(downsample(data, max_points))
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((GRANULARITY_HEADER, granularity.as_str()))
+        .json(data))
+}
+
+/// GET /api/custom/names
+/// Return the names of the custom metrics sent by a host
+pub async fn custom_names(
+    metrics: web::Data<MetricsPool>,
+    info: web::Query<SpecificDated>,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route GET /api/custom/names : {:?}", info);
+
+    let data = web::block(move || {
+        CustomMetricName::get_dated(
+            &mut metrics.pool.get()?,
+            &info.uuid,
+            info.min_date,
+            info.max_date,
+        )
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(data))
+}
+
+/// POST /api/custom
+/// Queue custom metrics to be saved under the host uuid
+/// The body can be JSON, MessagePack or CBOR, optionally compressed (see utils::payload)
+pub async fn custom_ingest(
+    req: HttpRequest,
+    info: web::Query<Specific>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route POST /api/custom");
+
+    let samples: Vec<CustomSample> = payload::decode(&req, &body)?;
+
+    let now = chrono::Utc::now().naive_utc();
+    let oldest = now - chrono::Duration::days(RAW_RETENTION_DAYS);
+    let mut rows = Rows::default();
+    let mut report = CustomReport::default();
+
+    for (index, sample) in samples.into_iter().enumerate() {
+        let created_at = sample.created_at.unwrap_or(now);
+
+        let mut reasons = Vec::new();
+        if !valid_metric_name(&sample.name) {
+            reasons.push(String::from("name must be between 1 and 128 characters"));
+        }
+        if !sample.value.is_finite() {
+            reasons.push(String::from("value is not a finite number"));
+        }
+        check_future(&mut reasons, created_at, now);
+        if created_at < oldest {
+            reasons.push(format!(
+                "created_at {} is older than the retention window",
+                created_at
+            ));
+        }
+
+        if !reasons.is_empty() {
+            report.rejected.push(RejectedSample {
+                batch: None,
+                index,
+                created_at,
+                reasons,
+            });
+            continue;
+        }
+
+        rows.customs.push(NewCustomMetric {
+            name: sample.name,
+            labels: serde_json::json!(sample.labels),
+            value: sample.value,
+            host_uuid: info.uuid.to_owned(),
+            created_at,
+        });
+    }
+
+    report.accepted = rows.customs.len();
+    if !rows.customs.is_empty() && !PIPELINE.enqueue(rows) {
+        return Ok(busy());
+    }
+
+    if report.accepted == 0 && !report.rejected.is_empty() {
+        Ok(HttpResponse::BadRequest().json(report))
+    } else {
+        Ok(HttpResponse::Ok().json(report))
+    }
+}
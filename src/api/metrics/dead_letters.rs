@@ -0,0 +1,101 @@
+use actix_web::{web, HttpResponse};
+use sproot::apierrors::ApiError;
+use sproot::models::MetricsPool;
+
+use crate::{
+    ingest::{busy, ingest_body, IngestBody, Outcome, FLUSH_ROUTE, PIPELINE},
+    models::{DeadLetter, DeadLetterEntry, Rows},
+    utils::payload::{self, Format},
+};
+
+use super::{
+    hosts::{all_rejected, report_response},
+    SpecificDeadLetter, SpecificPaged,
+};
+
+/// GET /api/dead_letters
+/// Return the dead letters (without their body) of a host, most recent first
+pub async fn dead_letters(
+    metrics: web::Data<MetricsPool>,
+    info: web::Query<SpecificPaged>,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route GET /api/dead_letters : {:?}", info);
+
+    let (size, page) = info.get_size_page()?;
+
+    let data =
+        web::block(move || DeadLetterEntry::get(&mut metrics.pool.get()?, &info.uuid, size, page))
+            .await??;
+
+    Ok(HttpResponse::Ok().json(data))
+}
+
+/// GET /api/dead_letters/entry
+/// Return a dead letter of a host, with its headers and (base64) body
+pub async fn dead_letters_entry(
+    metrics: web::Data<MetricsPool>,
+    info: web::Query<SpecificDeadLetter>,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route GET /api/dead_letters/entry : {:?}", info);
+
+    let data =
+        web::block(move || DeadLetter::get_specific(&mut metrics.pool.get()?, &info.uuid, info.id))
+            .await??;
+
+    Ok(HttpResponse::Ok().json(data))
+}
+
+/// POST /api/dead_letters/replay
+/// Ingest a dead letter again (once the cause of its rejection is fixed)
+/// Only the (verified) payloads of POST /api/hosts and the rows of failed flushes can be replayed,
+/// and only once: the letter is marked replayed before being ingested (unmarked on failure).
+pub async fn dead_letters_replay(
+    metrics: web::Data<MetricsPool>,
+    info: web::Query<SpecificDeadLetter>,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route POST /api/dead_letters/replay : {:?}", info);
+
+    let pool = metrics.clone();
+    let (uuid, id) = (info.uuid.to_owned(), info.id);
+    let letter = web::block(move || DeadLetter::claim(&mut pool.pool.get()?, &uuid, id)).await??;
+
+    let res = replay(&info.uuid, &letter);
+    if !matches!(res, Ok((_, true))) {
+        web::block(move || DeadLetter::release(&mut metrics.pool.get()?, id)).await??;
+    }
+
+    res.map(|(response, _)| response)
+}
+
+/// Ingest the dead letter, returning the response and whether it was replayed
+fn replay(uuid: &str, letter: &DeadLetter) -> Result<(HttpResponse, bool), ApiError> {
+    let header = |name: &str| letter.headers.get(name).and_then(|val| val.as_str());
+
+    match letter.route.as_str() {
+        "/api/hosts" => {
+            let format = Format::from_content_type(header("content-type"))?;
+            let body: IngestBody = payload::decode_as(format, &letter.body)?;
+            match ingest_body(uuid, body, header("sp-batch-id"))? {
+                Outcome::Queued { report, accepted } => {
+                    // Still rejected, the dead letter is left as is
+                    let replayed = !all_rejected(&report, accepted);
+                    Ok((report_response(report, accepted), replayed))
+                }
+                Outcome::Busy => Ok((busy(), false)),
+            }
+        }
+        FLUSH_ROUTE => {
+            let rows: Rows = serde_json::from_slice(&letter.body).map_err(|err| {
+                ApiError::InvalidRequestError(Some(format!("undecodable rows: {}", err)))
+            })?;
+            if !PIPELINE.enqueue(rows) {
+                return Ok((busy(), false));
+            }
+            Ok((HttpResponse::Ok().finish(), true))
+        }
+        other => Err(ApiError::InvalidRequestError(Some(format!(
+            "the payloads of {} can't be replayed",
+            other
+        )))),
+    }
+}
@@ -4,7 +4,9 @@ use sproot::models::BaseMetrics;
 use sproot::models::Disk;
 use sproot::models::MetricsPool;
 
-use super::SpecificDated;
+use crate::models::{downsample, DiskPoint};
+
+use super::{dated_response, SpecificDated};
 
 /// GET /api/disks
 /// Return disks for a particular host
@@ -14,15 +16,15 @@ pub async fn disks(
 ) -> Result<HttpResponse, ApiError> {
     trace!("Route GET /api/disks : {:?}", info);
 
-    let data = web::block(move || {
-        Disk::get_dated(
-            &mut metrics.pool.get()?,
-            &info.uuid,
-            info.min_date,
-            info.max_date,
-        )
-    })
-    .await??;
-
-    Ok(HttpResponse::Ok().json(data))
+    dated_response(
+        metrics,
+        info.into_inner(),
+        Disk::get_dated,
+        |conn, info, granularity, max_points| {
+            let data =
+                DiskPoint::get_dated(conn, &info.uuid, granularity, info.min_date, info.max_date)?;
+            Ok(downsample(data, max_points))
+        },
+    )
+    .await
 }
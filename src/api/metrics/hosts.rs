@@ -1,8 +1,17 @@
-use actix_web::{web, HttpResponse};
-use sproot::models::{BaseCrud, Host, HttpHost, MetricsPool};
+use actix_web::{web, HttpRequest, HttpResponse};
+use sproot::models::{BaseCrud, Host, MetricsPool};
 use sproot::{apierrors::ApiError, models::Specific};
 use {
-    crate::{api::get_user_session, AUTHPOOL},
+    crate::{
+        api::get_user_session,
+        ingest::{
+            busy, capture, ingest_body, push_valid_samples, IngestBody, IngestHost, IngestReport,
+            Outcome,
+        },
+        models::{refresh_aggregates, HostDetails, Rows, RAW_RETENTION_DAYS},
+        utils::payload,
+        AUTHPOOL,
+    },
     actix_session::Session,
     sproot::models::ApiKey,
 };
@@ -37,30 +46,126 @@ pub async fn host_all(
 }
 
 /// GET /api/host
-/// Return info for a specific host
+/// Return info for a specific host (including its clock skew)
 pub async fn host_specific(
     metrics: web::Data<MetricsPool>,
     info: web::Query<SpecificPaged>,
 ) -> Result<HttpResponse, ApiError> {
     trace!("Route GET /api/host?uuid=xyz");
 
-    let data =
-        web::block(move || Host::get_specific(&mut metrics.pool.get()?, &info.uuid)).await??;
+    let data = web::block(move || HostDetails::get_specific(&mut metrics.pool.get()?, &info.uuid))
+        .await??;
 
     Ok(HttpResponse::Ok().json(data))
 }
 
 /// POST /api/hosts
-/// Save data from a host into the db under his uuid
+/// Queue data from a host to be saved into the db under his uuid
+/// The body can be JSON, MessagePack or CBOR, optionally compressed (see utils::payload)
+/// Batches carrying an id (in the body or the SP-BATCH-ID header) are only applied once.
+/// Invalid samples are skipped, the response then reports the applied, deduplicated
+/// and rejected batches/samples.
+/// Undecodable bodies and bodies whose samples were all rejected are dead lettered.
+/// The skew of the host's clock is recorded and handled according to clock_skew_policy.
 pub async fn host_ingest(
-    metrics: web::Data<MetricsPool>,
+    req: HttpRequest,
     info: web::Query<Specific>,
-    item: web::Json<Vec<HttpHost>>,
+    body: web::Bytes,
 ) -> Result<HttpResponse, ApiError> {
     trace!("Route POST /api/guard/hosts");
 
-    web::block(move || Host::insert(&mut metrics.pool.get()?, &item.into_inner(), &info.uuid))
+    let header_id = match req.headers().get("SP-BATCH-ID") {
+        Some(val) => Some(val.to_str().map_err(|_| {
+            ApiError::InvalidRequestError(Some(String::from("invalid SP-BATCH-ID header")))
+        })?),
+        None => None,
+    };
+
+    let decoded: IngestBody = match payload::decode(&req, &body) {
+        Ok(decoded) => decoded,
+        Err(err) => {
+            capture(&req, &body, err.to_string()).await;
+            return Err(err);
+        }
+    };
+
+    match ingest_body(&info.uuid, decoded, header_id)? {
+        Outcome::Queued { report, accepted } => {
+            if all_rejected(&report, accepted) {
+                let reasons = report
+                    .rejected
+                    .iter()
+                    .flat_map(|sample| sample.reasons.iter().map(String::as_str))
+                    .collect::<Vec<_>>();
+                let reason = format!("every sample was rejected: {}", reasons.join(", "));
+                capture(&req, &body, reason).await;
+            }
+            Ok(report_response(report, accepted))
+        }
+        Outcome::Busy => Ok(busy()),
+    }
+}
+
+/// POST /api/hosts/backfill
+/// Save old data from a host (buffered while offline) under his uuid
+/// and refresh the aggregated views covering it
+pub async fn host_backfill(
+    req: HttpRequest,
+    metrics: web::Data<MetricsPool>,
+    info: web::Query<Specific>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route POST /api/hosts/backfill");
+
+    let items: Vec<IngestHost> = payload::decode(&req, &body)?;
+
+    // Older samples would be dropped by the next run of the retention policy
+    let cutoff = chrono::Utc::now().naive_utc() - chrono::Duration::days(RAW_RETENTION_DAYS);
+    let too_old = items
+        .iter()
+        .filter(|item| item.host.created_at < cutoff)
+        .count();
+    if too_old > 0 {
+        return Err(ApiError::InvalidRequestError(Some(format!(
+            "{} samples are older than the retention window of {} days (before {})",
+            too_old, RAW_RETENTION_DAYS, cutoff
+        ))));
+    }
+
+    let mut rows = Rows::default();
+    let mut report = IngestReport::default();
+    push_valid_samples(&mut rows, &mut report.rejected, &info.uuid, None, &items);
+
+    let accepted = !rows.is_empty();
+    if accepted {
+        web::block(move || {
+            let mut conn = metrics.pool.get()?;
+            rows.insert(&mut conn)?;
+            for (table, min, max) in rows.aggregated_ranges() {
+                refresh_aggregates(&mut conn, table, min, max)?;
+            }
+            Ok::<_, ApiError>(())
+        })
         .await??;
+    }
+
+    Ok(report_response(report, accepted))
+}
+
+/// Whether every sample of the body was rejected (nothing applied nor deduplicated)
+pub(super) fn all_rejected(report: &IngestReport, accepted: bool) -> bool {
+    !accepted && report.deduplicated.is_empty() && !report.rejected.is_empty()
+}
+
+/// 400 with the report if every sample was rejected, 200 otherwise
+/// (with the report, unless there's nothing to tell).
+pub(super) fn report_response(report: IngestReport, accepted: bool) -> HttpResponse {
+    if all_rejected(&report, accepted) {
+        return HttpResponse::BadRequest().json(report);
+    }
+    if report.rejected.is_empty() && report.applied.is_empty() && report.deduplicated.is_empty() {
+        return HttpResponse::Ok().finish();
+    }
 
-    Ok(HttpResponse::Ok().finish())
+    HttpResponse::Ok().json(report)
 }
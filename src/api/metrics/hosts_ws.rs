@@ -0,0 +1,190 @@
+//! WebSocket ingest channel: the agent is authenticated once (by the
+//! SptkValidator, on the upgrade request) and then streams its samples.
+//! Each data frame has the same shape as the body of POST /api/hosts,
+//! encoded as JSON (text frames) or MessagePack (binary frames). The
+//! server answers every data frame (numbered from 1 in `seq`) and pushes
+//! the sync_interval of the host when the connection opens and each
+//! time it changes.
+use actix_web::rt::time::interval;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::{AggregatedMessage, AggregatedMessageStream, CloseCode, CloseReason, Session};
+use serde::Serialize;
+use sproot::apierrors::ApiError;
+use sproot::models::Specific;
+use std::time::{Duration, Instant};
+
+use crate::{
+    auth::ingest_limiter::{fetch_sync_interval, take_token},
+    ingest::{ingest_body, IngestBody, IngestReport, Outcome},
+    utils::payload::{self, Format},
+    CONFIG,
+};
+
+/// How often a ping is sent to the agent
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+/// The connection is closed if nothing is received for this long
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(90);
+/// How often the sync_interval of the host is checked for changes
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// Agents have to reconnect (and thus be authenticated again) after this
+const MAX_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Frames sent by the server
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerFrame {
+    Ack {
+        seq: u64,
+        #[serde(flatten)]
+        report: IngestReport,
+    },
+    Error {
+        seq: u64,
+        error: String,
+    },
+    Busy {
+        seq: u64,
+        retry_after: u64,
+    },
+    RateLimited {
+        seq: u64,
+        retry_after: u64,
+    },
+    Config {
+        sync_interval: i64,
+    },
+}
+
+/// GET /api/hosts/ws
+/// Open a WebSocket used by a host to stream its data
+pub async fn host_stream(
+    req: HttpRequest,
+    info: web::Query<Specific>,
+    body: web::Payload,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route GET /api/hosts/ws");
+
+    let (response, mut session, stream) = actix_ws::handle(&req, body)
+        .map_err(|err| ApiError::InvalidRequestError(Some(err.to_string())))?;
+
+    let stream = stream
+        .max_frame_size(payload::MAX_PAYLOAD_SIZE)
+        .aggregate_continuations()
+        .max_continuation_size(payload::MAX_PAYLOAD_SIZE);
+
+    let uuid = info.into_inner().uuid;
+    actix_web::rt::spawn(async move {
+        let reason = stream_loop(&uuid, &mut session, stream).await;
+        trace!("host_stream: closing the stream of {}", uuid);
+        let _ = session.close(reason).await;
+    });
+
+    Ok(response)
+}
+
+/// Handle the stream until it is closed, return the reason to close it with
+async fn stream_loop(
+    uuid: &str,
+    session: &mut Session,
+    mut stream: AggregatedMessageStream,
+) -> Option<CloseReason> {
+    let started = Instant::now();
+    let mut last_seen = Instant::now();
+    let mut heartbeat = interval(HEARTBEAT_INTERVAL);
+    let mut config_poll = interval(CONFIG_POLL_INTERVAL);
+    let mut sync_interval = None;
+    let mut seq = 0;
+
+    loop {
+        let frame = tokio::select! {
+            msg = stream.recv() => {
+                last_seen = Instant::now();
+                match msg {
+                    Some(Ok(AggregatedMessage::Text(text))) => {
+                        seq += 1;
+                        handle_frame(uuid, seq, Format::Json, text.as_bytes(), sync_interval)
+                    }
+                    Some(Ok(AggregatedMessage::Binary(bytes))) => {
+                        seq += 1;
+                        handle_frame(uuid, seq, Format::MsgPack, &bytes, sync_interval)
+                    }
+                    Some(Ok(AggregatedMessage::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            return None;
+                        }
+                        continue;
+                    }
+                    Some(Ok(AggregatedMessage::Pong(_))) => continue,
+                    Some(Ok(AggregatedMessage::Close(reason))) => return reason,
+                    Some(Err(err)) => {
+                        debug!("host_stream: protocol error from {}: {}", uuid, err);
+                        return Some(CloseCode::Protocol.into());
+                    }
+                    None => return None,
+                }
+            }
+            _ = heartbeat.tick() => {
+                if last_seen.elapsed() > CLIENT_TIMEOUT {
+                    return Some(CloseCode::Away.into());
+                }
+                if started.elapsed() > MAX_LIFETIME {
+                    return Some(CloseReason {
+                        code: CloseCode::Again,
+                        description: Some(String::from("connection lifetime exceeded")),
+                    });
+                }
+                if session.ping(b"").await.is_err() {
+                    return None;
+                }
+                continue;
+            }
+            _ = config_poll.tick() => {
+                match fetch_sync_interval(uuid).await {
+                    Ok(value) if sync_interval != Some(value) => {
+                        sync_interval = Some(value);
+                        ServerFrame::Config { sync_interval: value }
+                    }
+                    Ok(_) => continue,
+                    Err(err) => {
+                        error!("host_stream: cannot get the sync_interval of {}: {}", uuid, err);
+                        continue;
+                    }
+                }
+            }
+        };
+
+        // ServerFrame only holds strings and numbers, it can't fail to serialize
+        let text = serde_json::to_string(&frame).unwrap_or_default();
+        if session.text(text).await.is_err() {
+            return None;
+        }
+    }
+}
+
+/// Ingest a data frame and build the answer to send back
+fn handle_frame(
+    uuid: &str,
+    seq: u64,
+    format: Format,
+    bytes: &[u8],
+    sync_interval: Option<i64>,
+) -> ServerFrame {
+    if let Err(retry_after) = take_token(uuid, sync_interval.unwrap_or(1)) {
+        return ServerFrame::RateLimited { seq, retry_after };
+    }
+
+    let res = payload::decode_as::<IngestBody>(format, bytes)
+        .and_then(|body| ingest_body(uuid, body, None));
+
+    match res {
+        Ok(Outcome::Queued { report, .. }) => ServerFrame::Ack { seq, report },
+        Ok(Outcome::Busy) => ServerFrame::Busy {
+            seq,
+            retry_after: CONFIG.ingest_flush_interval.max(1),
+        },
+        Err(err) => ServerFrame::Error {
+            seq,
+            error: err.to_string(),
+        },
+    }
+}
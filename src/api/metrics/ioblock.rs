@@ -4,25 +4,32 @@ use sproot::models::BaseMetrics;
 use sproot::models::IoBlock;
 use sproot::models::MetricsPool;
 
-use super::SpecificDated;
+use crate::models::{CounterRows, IoBlockPoint};
+
+use super::{dated_response, CounterDated};
 
 /// GET /api/ioblocks
 /// Return ioblock for a particular host
 pub async fn ioblocks(
     metrics: web::Data<MetricsPool>,
-    info: web::Query<SpecificDated>,
+    info: web::Query<CounterDated>,
 ) -> Result<HttpResponse, ApiError> {
     trace!("Route GET /api/ioblocks : {:?}", info);
 
-    let data = web::block(move || {
-        IoBlock::get_dated(
-            &mut metrics.pool.get()?,
-            &info.uuid,
-            info.min_date,
-            info.max_date,
-        )
-    })
-    .await??;
-
-    Ok(HttpResponse::Ok().json(data))
+    dated_response(
+        metrics,
+        info.into_inner(),
+        IoBlock::get_dated,
+        |conn, info, granularity, max_points| {
+            let data = IoBlockPoint::get_dated(
+                conn,
+                &info.uuid,
+                granularity,
+                info.min_date,
+                info.max_date,
+            )?;
+            Ok(CounterRows::new(data, info.rate, max_points))
+        },
+    )
+    .await
 }
@@ -4,25 +4,27 @@ use sproot::models::BaseMetrics;
 use sproot::models::IoNet;
 use sproot::models::MetricsPool;
 
-use super::SpecificDated;
+use crate::models::{CounterRows, IoNetPoint};
+
+use super::{dated_response, CounterDated};
 
 /// GET /api/ionets
 /// Return ionets for a particular host
 pub async fn ionets(
     metrics: web::Data<MetricsPool>,
-    info: web::Query<SpecificDated>,
+    info: web::Query<CounterDated>,
 ) -> Result<HttpResponse, ApiError> {
     trace!("Route GET /api/ionets : {:?}", info);
 
-    let data = web::block(move || {
-        IoNet::get_dated(
-            &mut metrics.pool.get()?,
-            &info.uuid,
-            info.min_date,
-            info.max_date,
-        )
-    })
-    .await??;
-
-    Ok(HttpResponse::Ok().json(data))
+    dated_response(
+        metrics,
+        info.into_inner(),
+        IoNet::get_dated,
+        |conn, info, granularity, max_points| {
+            let data =
+                IoNetPoint::get_dated(conn, &info.uuid, granularity, info.min_date, info.max_date)?;
+            Ok(CounterRows::new(data, info.rate, max_points))
+        },
+    )
+    .await
 }
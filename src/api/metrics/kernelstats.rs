@@ -0,0 +1,34 @@
+use actix_web::{web, HttpResponse};
+use sproot::apierrors::ApiError;
+use sproot::models::MetricsPool;
+
+use crate::models::{downsample, KernelStats};
+
+use super::{Dated, SpecificDated, GRANULARITY_HEADER};
+
+/// GET /api/kernelstats
+/// Return the kernel resources (fds, conntrack, entropy) for a particular host
+pub async fn kernelstats(
+    metrics: web::Data<MetricsPool>,
+    info: web::Query<SpecificDated>,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route GET /api/kernelstats : {:?}", info);
+
+    let granularity = info.get_granularity();
+    let max_points = info.get_max_points()?;
+    let data = web::block(move || {
+        let data = KernelStats::get_dated(
+            &mut metrics.pool.get()?,
+            &info.uuid,
+            granularity,
+            info.min_date,
+            info.max_date,
+        )?;
+        Ok::<_, ApiError>(downsample(data, max_points))
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((GRANULARITY_HEADER, granularity.as_str()))
+        .json(data))
+}
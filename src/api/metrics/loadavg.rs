@@ -4,7 +4,9 @@ use sproot::models::BaseMetrics;
 use sproot::models::LoadAvg;
 use sproot::models::MetricsPool;
 
-use super::SpecificDated;
+use crate::models::{downsample, LoadAvgPoint};
+
+use super::{dated_response, SpecificDated};
 
 /// GET /api/load_avg
 /// Return load_avg for a particular host
@@ -14,15 +16,20 @@ pub async fn loadavg(
 ) -> Result<HttpResponse, ApiError> {
     trace!("Route GET /api/loadavg : {:?}", info);
 
-    let data = web::block(move || {
-        LoadAvg::get_dated(
-            &mut metrics.pool.get()?,
-            &info.uuid,
-            info.min_date,
-            info.max_date,
-        )
-    })
-    .await??;
-
-    Ok(HttpResponse::Ok().json(data))
+    dated_response(
+        metrics,
+        info.into_inner(),
+        LoadAvg::get_dated,
+        |conn, info, granularity, max_points| {
+            let data = LoadAvgPoint::get_dated(
+                conn,
+                &info.uuid,
+                granularity,
+                info.min_date,
+                info.max_date,
+            )?;
+            Ok(downsample(data, max_points))
+        },
+    )
+    .await
 }
@@ -4,7 +4,9 @@ use sproot::models::BaseMetrics;
 use sproot::models::Memory;
 use sproot::models::MetricsPool;
 
-use super::SpecificDated;
+use crate::models::{downsample, MemoryPoint};
+
+use super::{dated_response, SpecificDated};
 
 /// GET /api/memory
 /// Return swap for a particular host
@@ -14,15 +16,20 @@ pub async fn memory(
 ) -> Result<HttpResponse, ApiError> {
     trace!("Route GET /api/memory : {:?}", info);
 
-    let data = web::block(move || {
-        Memory::get_dated(
-            &mut metrics.pool.get()?,
-            &info.uuid,
-            info.min_date,
-            info.max_date,
-        )
-    })
-    .await??;
-
-    Ok(HttpResponse::Ok().json(data))
+    dated_response(
+        metrics,
+        info.into_inner(),
+        Memory::get_dated,
+        |conn, info, granularity, max_points| {
+            let data = MemoryPoint::get_dated(
+                conn,
+                &info.uuid,
+                granularity,
+                info.min_date,
+                info.max_date,
+            )?;
+            Ok(downsample(data, max_points))
+        },
+    )
+    .await
 }
@@ -2,19 +2,38 @@
 //! synchronous operation (access to Diesel's conns) allowing
 //! Actix to handle another request while the sync task is
 //! being performed.
+use actix_web::{web, HttpResponse};
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use sproot::apierrors::ApiError;
+use sproot::models::MetricsPool;
+use sproot::ConnType;
+
+use crate::models::{Granularity, ProcessSort, MIN_POINTS};
 use {actix_session::Session, uuid::Uuid};
 
+pub mod containers;
 pub mod cpustats;
 pub mod cputimes;
+pub mod custom;
+pub mod dead_letters;
 pub mod disks;
 pub mod hosts;
+pub mod hosts_ws;
 pub mod ioblock;
 pub mod ionet;
+pub mod kernelstats;
 pub mod loadavg;
 pub mod memory;
+pub mod processes;
+pub mod sensors;
+pub mod smart;
 pub mod swap;
+pub mod tcpstates;
+pub mod units;
+
+/// Header of the responses telling the granularity the metrics were read at
+pub const GRANULARITY_HEADER: &str = "SP-GRANULARITY";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Paged {
@@ -40,6 +59,57 @@ pub struct SpecificDated {
     pub uuid: String,
     pub min_date: chrono::NaiveDateTime,
     pub max_date: chrono::NaiveDateTime,
+    pub granularity: Option<Granularity>,
+    pub max_points: Option<usize>,
+}
+
+/// SpecificDated of the counters tables, which can be read as per-second rates
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CounterDated {
+    pub uuid: String,
+    pub min_date: chrono::NaiveDateTime,
+    pub max_date: chrono::NaiveDateTime,
+    pub granularity: Option<Granularity>,
+    pub max_points: Option<usize>,
+    #[serde(default)]
+    pub rate: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CustomDated {
+    pub uuid: String,
+    pub name: Option<String>,
+    pub min_date: chrono::NaiveDateTime,
+    pub max_date: chrono::NaiveDateTime,
+    pub granularity: Option<Granularity>,
+    pub max_points: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContainerDated {
+    pub uuid: String,
+    pub container_id: Option<String>,
+    pub min_date: chrono::NaiveDateTime,
+    pub max_date: chrono::NaiveDateTime,
+    pub granularity: Option<Granularity>,
+    pub max_points: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnitDated {
+    pub uuid: String,
+    pub unit: Option<String>,
+    pub min_date: chrono::NaiveDateTime,
+    pub max_date: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProcessesDated {
+    pub uuid: String,
+    pub min_date: chrono::NaiveDateTime,
+    pub max_date: chrono::NaiveDateTime,
+    pub sort: Option<ProcessSort>,
+    pub limit: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -56,6 +126,12 @@ pub struct OptSpecificPaged {
     pub page: Option<i64>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SpecificDeadLetter {
+    pub uuid: String,
+    pub id: i64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SpecificAlert {
     pub id: i64,
@@ -74,6 +150,125 @@ impl SpecificPaged {
     }
 }
 
+/// The queries of the dated routes, optionally read at a granularity
+/// and downsampled to max_points
+pub trait Dated {
+    fn uuid(&self) -> &str;
+    fn min_date(&self) -> NaiveDateTime;
+    fn max_date(&self) -> NaiveDateTime;
+    /// Whether only the date range is asked (the response of the sproot model is kept)
+    fn is_plain(&self) -> bool;
+    /// The granularity asked (raw if none), resolved for [min_date, max_date]
+    fn get_granularity(&self) -> Granularity;
+    /// The number of points per series to downsample to, if asked
+    fn get_max_points(&self) -> Result<Option<usize>, ApiError>;
+}
+
+/// Implement Dated, the flags listed ({ rate }) are asked on top of the range too
+macro_rules! impl_dated {
+    ($($dated:ident $({ $($flag:ident),* })?),*) => {
+        $(
+            impl Dated for $dated {
+                fn uuid(&self) -> &str {
+                    &self.uuid
+                }
+
+                fn min_date(&self) -> NaiveDateTime {
+                    self.min_date
+                }
+
+                fn max_date(&self) -> NaiveDateTime {
+                    self.max_date
+                }
+
+                fn is_plain(&self) -> bool {
+                    self.granularity.is_none() && self.max_points.is_none()
+                        $($(&& !self.$flag)*)?
+                }
+
+                fn get_granularity(&self) -> Granularity {
+                    self.granularity
+                        .unwrap_or_default()
+                        .resolve(self.min_date, self.max_date)
+                }
+
+                fn get_max_points(&self) -> Result<Option<usize>, ApiError> {
+                    match self.max_points {
+                        Some(v) if v < MIN_POINTS => Err(ApiError::ExplicitError(format!(
+                            "max_points must be >= {}",
+                            MIN_POINTS
+                        ))),
+                        v => Ok(v),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_dated!(
+    SpecificDated,
+    CounterDated { rate },
+    CustomDated,
+    ContainerDated
+);
+
+/// Respond to the dated routes of the tables sproot has a model of: the rows
+/// of the `sproot` model are kept as is if the query is plain, otherwise the
+/// rows of `points` are read at the granularity asked (told by SP-GRANULARITY).
+/// On top of the date range, every dated route (with a sproot model or not)
+/// takes these parameters:
+/// - granularity: raw/10m/30m/auto, the raw table is read if not asked
+/// - max_points: each series is downsampled (with LTTB) to max_points
+/// - rate (CounterDated only): the counters are returned as per-second rates (see models::rates)
+pub async fn dated_response<Q, S, P>(
+    metrics: web::Data<MetricsPool>,
+    info: Q,
+    sproot: fn(&mut ConnType, &str, NaiveDateTime, NaiveDateTime) -> Result<S, ApiError>,
+    points: impl FnOnce(&mut ConnType, &Q, Granularity, Option<usize>) -> Result<P, ApiError>
+        + Send
+        + 'static,
+) -> Result<HttpResponse, ApiError>
+where
+    Q: Dated + Send + 'static,
+    S: Serialize + Send + 'static,
+    P: Serialize + Send + 'static,
+{
+    if info.is_plain() {
+        let data = web::block(move || {
+            sproot(
+                &mut metrics.pool.get()?,
+                info.uuid(),
+                info.min_date(),
+                info.max_date(),
+            )
+        })
+        .await??;
+
+        return Ok(HttpResponse::Ok().json(data));
+    }
+
+    let granularity = info.get_granularity();
+    let max_points = info.get_max_points()?;
+    let data = web::block(move || points(&mut metrics.pool.get()?, &info, granularity, max_points))
+        .await??;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((GRANULARITY_HEADER, granularity.as_str()))
+        .json(data))
+}
+
+impl ProcessesDated {
+    pub fn get_limit(&self) -> Result<i64, ApiError> {
+        match self.limit.unwrap_or(10) {
+            v if v > 0 && v <= 100 => Ok(v),
+            _ => Err(ApiError::ExplicitError(String::from(
+                "limit must be > 0 && <= 100",
+            ))),
+        }
+    }
+}
+
 impl OptSpecificPaged {
     pub fn get_size_page(&self) -> Result<(i64, i64), ApiError> {
         let size = self.size.unwrap_or(100);
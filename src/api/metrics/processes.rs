@@ -0,0 +1,32 @@
+use actix_web::{web, HttpResponse};
+use sproot::apierrors::ApiError;
+use sproot::models::MetricsPool;
+
+use crate::models::TopProcess;
+
+use super::ProcessesDated;
+
+/// GET /api/processes
+/// Return the top processes (by cpu or memory) of a host
+pub async fn processes(
+    metrics: web::Data<MetricsPool>,
+    info: web::Query<ProcessesDated>,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route GET /api/processes : {:?}", info);
+
+    let limit = info.get_limit()?;
+
+    let data = web::block(move || {
+        TopProcess::get_top(
+            &mut metrics.pool.get()?,
+            &info.uuid,
+            info.min_date,
+            info.max_date,
+            info.sort.unwrap_or_default(),
+            limit,
+        )
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(data))
+}
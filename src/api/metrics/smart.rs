@@ -0,0 +1,34 @@
+use actix_web::{web, HttpResponse};
+use sproot::apierrors::ApiError;
+use sproot::models::MetricsPool;
+
+use crate::models::{downsample, Smart};
+
+use super::{Dated, SpecificDated, GRANULARITY_HEADER};
+
+/// GET /api/smart
+/// Return the SMART attributes of the disks for a particular host
+pub async fn smart(
+    metrics: web::Data<MetricsPool>,
+    info: web::Query<SpecificDated>,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route GET /api/smart : {:?}", info);
+
+    let granularity = info.get_granularity();
+    let max_points = info.get_max_points()?;
+    let data = web::block(move || {
+        let data = Smart::get_dated(
+            &mut metrics.pool.get()?,
+            &info.uuid,
+            granularity,
+            info.min_date,
+            info.max_date,
+        )?;
+        Ok::<_, ApiError>(downsample(data, max_points))
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((GRANULARITY_HEADER, granularity.as_str()))
+        .json(data))
+}
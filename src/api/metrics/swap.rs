@@ -4,7 +4,9 @@ use sproot::models::BaseMetrics;
 use sproot::models::MetricsPool;
 use sproot::models::Swap;
 
-use super::SpecificDated;
+use crate::models::{downsample, SwapPoint};
+
+use super::{dated_response, SpecificDated};
 
 /// GET /api/swap
 /// Return swap for a particular host
@@ -14,15 +16,15 @@ pub async fn swap(
 ) -> Result<HttpResponse, ApiError> {
     trace!("Route GET /api/swap : {:?}", info);
 
-    let data = web::block(move || {
-        Swap::get_dated(
-            &mut metrics.pool.get()?,
-            &info.uuid,
-            info.min_date,
-            info.max_date,
-        )
-    })
-    .await??;
-
-    Ok(HttpResponse::Ok().json(data))
+    dated_response(
+        metrics,
+        info.into_inner(),
+        Swap::get_dated,
+        |conn, info, granularity, max_points| {
+            let data =
+                SwapPoint::get_dated(conn, &info.uuid, granularity, info.min_date, info.max_date)?;
+            Ok(downsample(data, max_points))
+        },
+    )
+    .await
 }
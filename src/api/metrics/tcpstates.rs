@@ -0,0 +1,34 @@
+use actix_web::{web, HttpResponse};
+use sproot::apierrors::ApiError;
+use sproot::models::MetricsPool;
+
+use crate::models::{downsample, TcpStates};
+
+use super::{Dated, SpecificDated, GRANULARITY_HEADER};
+
+/// GET /api/tcpstates
+/// Return the TCP sockets per state for a particular host
+pub async fn tcpstates(
+    metrics: web::Data<MetricsPool>,
+    info: web::Query<SpecificDated>,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route GET /api/tcpstates : {:?}", info);
+
+    let granularity = info.get_granularity();
+    let max_points = info.get_max_points()?;
+    let data = web::block(move || {
+        let data = TcpStates::get_dated(
+            &mut metrics.pool.get()?,
+            &info.uuid,
+            granularity,
+            info.min_date,
+            info.max_date,
+        )?;
+        Ok::<_, ApiError>(downsample(data, max_points))
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((GRANULARITY_HEADER, granularity.as_str()))
+        .json(data))
+}
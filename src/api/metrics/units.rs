@@ -0,0 +1,43 @@
+use actix_web::{web, HttpResponse};
+use sproot::apierrors::ApiError;
+use sproot::models::{MetricsPool, Specific};
+
+use crate::models::{UnitState, UnitTransition};
+
+use super::UnitDated;
+
+/// GET /api/units
+/// Return the current state of the systemd units of a host
+pub async fn units(
+    metrics: web::Data<MetricsPool>,
+    info: web::Query<Specific>,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route GET /api/units : {:?}", info);
+
+    let data =
+        web::block(move || UnitState::get_specific(&mut metrics.pool.get()?, &info.uuid)).await??;
+
+    Ok(HttpResponse::Ok().json(data))
+}
+
+/// GET /api/units/timeline
+/// Return the state transitions (optionally of a single unit) of a host
+pub async fn units_timeline(
+    metrics: web::Data<MetricsPool>,
+    info: web::Query<UnitDated>,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route GET /api/units/timeline : {:?}", info);
+
+    let data = web::block(move || {
+        UnitTransition::get_dated(
+            &mut metrics.pool.get()?,
+            &info.uuid,
+            info.unit.as_deref(),
+            info.min_date,
+            info.max_date,
+        )
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(data))
+}
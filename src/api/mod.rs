@@ -1,5 +1,7 @@
 mod balerts;
 mod metrics;
+mod receivers;
 
 pub use balerts::*;
 pub use metrics::*;
+pub use receivers::*;
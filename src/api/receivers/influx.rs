@@ -0,0 +1,423 @@
+//! InfluxDB line protocol receiver, meant for Telegraf agents.
+//! The measurements of Telegraf's cpu, mem, swap, system, disk, diskio,
+//! net, kernel and processes inputs are mapped onto our own tables. The
+//! cpu input must be configured with `collect_cpu_time = true` and only
+//! the `cpu-total` lines are used (the per cpu lines are skipped).
+//!
+//! Lines which can't be parsed or mapped are rejected individually,
+//! the other ones are still saved and a report listing the rejected
+//! lines is sent back with a 400.
+use actix_web::{web, HttpResponse};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sproot::apierrors::ApiError;
+use std::collections::BTreeMap;
+
+use crate::ingest::{busy, Snapshot, STAGING};
+
+use super::{PSEUDO_FS, USER_HZ};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InfluxWrite {
+    pub uuid: String,
+    /// Precision of the timestamps (ns, us, ms or s), default to ns
+    pub precision: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InfluxReport {
+    pub accepted: usize,
+    pub rejected: Vec<RejectedLine>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RejectedLine {
+    /// Line number, starting at 1
+    pub line: usize,
+    pub error: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum FieldValue {
+    Float(f64),
+    Int(i64),
+    UInt(u64),
+    Str(String),
+    Bool(bool),
+}
+
+impl FieldValue {
+    fn number(&self) -> Option<f64> {
+        match self {
+            FieldValue::Float(val) => Some(*val),
+            FieldValue::Int(val) => Some(*val as f64),
+            FieldValue::UInt(val) => Some(*val as f64),
+            FieldValue::Str(_) | FieldValue::Bool(_) => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Line {
+    measurement: String,
+    tags: BTreeMap<String, String>,
+    fields: BTreeMap<String, FieldValue>,
+    timestamp: Option<i64>,
+}
+
+impl Line {
+    fn tag(&self, key: &str) -> &str {
+        self.tags.get(key).map(String::as_str).unwrap_or_default()
+    }
+
+    fn field(&self, key: &str) -> Option<i64> {
+        self.fields
+            .get(key)
+            .and_then(FieldValue::number)
+            .map(|val| val as i64)
+    }
+}
+
+/// POST /api/influx/write
+/// Save the lines of an InfluxDB line protocol payload under the host uuid
+pub async fn influx_write(
+    info: web::Query<InfluxWrite>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route POST /api/influx/write");
+
+    let body = std::str::from_utf8(&body)
+        .map_err(|_| ApiError::InvalidRequestError(Some(String::from("body is not utf8"))))?;
+
+    let multiplier = match info.precision.as_deref() {
+        None | Some("ns") | Some("n") => 1,
+        Some("us") | Some("u") => 1_000,
+        Some("ms") => 1_000_000,
+        Some("s") => 1_000_000_000,
+        Some(other) => {
+            return Err(ApiError::InvalidRequestError(Some(format!(
+                "unknown precision '{}'",
+                other
+            ))))
+        }
+    };
+
+    let now = chrono::Utc::now().naive_utc();
+    let mut report = InfluxReport {
+        accepted: 0,
+        rejected: Vec::new(),
+    };
+
+    let staged = STAGING.with(&info.uuid, |stage| {
+        for (idx, raw) in body.lines().enumerate() {
+            let raw = raw.trim();
+            if raw.is_empty() || raw.starts_with('#') {
+                continue;
+            }
+
+            let res = parse_line(raw).and_then(|line| {
+                let created_at = match line.timestamp {
+                    Some(ts) => timestamp_from_nanos(ts.saturating_mul(multiplier))
+                        .ok_or_else(|| String::from("invalid timestamp"))?,
+                    None => now,
+                };
+                map_telegraf(stage.at(created_at), &line)
+            });
+
+            match res {
+                Ok(()) => report.accepted += 1,
+                Err(error) => report.rejected.push(RejectedLine {
+                    line: idx + 1,
+                    error,
+                }),
+            }
+        }
+    });
+
+    if !staged {
+        return Ok(busy());
+    }
+
+    if report.rejected.is_empty() {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        debug!(
+            "influx_write: {} lines rejected for {}",
+            report.rejected.len(),
+            info.uuid
+        );
+        Ok(HttpResponse::BadRequest().json(report))
+    }
+}
+
+fn timestamp_from_nanos(nanos: i64) -> Option<NaiveDateTime> {
+    (nanos > 0).then(|| chrono::DateTime::from_timestamp_nanos(nanos).naive_utc())
+}
+
+/// Map a Telegraf line onto the Snapshot
+fn map_telegraf(snapshot: &mut Snapshot, line: &Line) -> Result<(), String> {
+    let field = |key: &str| line.field(key).unwrap_or_default();
+
+    if !line.tag("host").is_empty() {
+        snapshot.host.hostname = Some(line.tag("host").to_owned());
+    }
+
+    match line.measurement.as_str() {
+        "cpu" => {
+            if line.tag("cpu") != "cpu-total" {
+                return Ok(());
+            }
+            let mut found = false;
+            for (key, value) in &line.fields {
+                let (Some(column), Some(secs)) = (key.strip_prefix("time_"), value.number()) else {
+                    continue;
+                };
+                snapshot.cpu_time("total", column, (secs * USER_HZ) as i64);
+                found = true;
+            }
+            if !found {
+                return Err(String::from(
+                    "cpu: no time_* fields, set collect_cpu_time = true",
+                ));
+            }
+        }
+        "mem" => {
+            let memory = snapshot.memory();
+            memory.total = field("total");
+            memory.free = field("free");
+            memory.used = field("used");
+            memory.shared = field("shared");
+            memory.buffers = field("buffered");
+            memory.cached = field("cached");
+        }
+        "swap" => {
+            let swap = snapshot.swap();
+            swap.total = field("total");
+            swap.free = field("free");
+            swap.used = field("used");
+        }
+        "system" => {
+            if let Some(uptime) = line.field("uptime") {
+                snapshot.host.uptime = Some(uptime);
+            }
+            if line.fields.contains_key("load1") {
+                let loadavg = snapshot.loadavg();
+                let load = |key: &str| line.fields.get(key).and_then(FieldValue::number);
+                loadavg.one = load("load1").unwrap_or_default();
+                loadavg.five = load("load5").unwrap_or_default();
+                loadavg.fifteen = load("load15").unwrap_or_default();
+            }
+        }
+        "disk" => {
+            if PSEUDO_FS.contains(&line.tag("fstype")) {
+                return Ok(());
+            }
+            let disk = snapshot.disk(line.tag("device"), line.tag("path"));
+            disk.total_space = field("total");
+            disk.avail_space = field("free");
+        }
+        "diskio" => {
+            let ioblock = snapshot.ioblock(line.tag("name"));
+            ioblock.read_count = field("reads");
+            ioblock.read_bytes = field("read_bytes");
+            ioblock.write_count = field("writes");
+            ioblock.write_bytes = field("write_bytes");
+            ioblock.busy_time = field("io_time");
+        }
+        "net" => {
+            // The "all" interface only carries the protocols stats
+            if line.tag("interface") == "all" {
+                return Ok(());
+            }
+            let ionet = snapshot.ionet(line.tag("interface"));
+            ionet.rx_bytes = field("bytes_recv");
+            ionet.rx_packets = field("packets_recv");
+            ionet.rx_errs = field("err_in");
+            ionet.rx_drop = field("drop_in");
+            ionet.tx_bytes = field("bytes_sent");
+            ionet.tx_packets = field("packets_sent");
+            ionet.tx_errs = field("err_out");
+            ionet.tx_drop = field("drop_out");
+        }
+        "kernel" => {
+            let cpustats = snapshot.cpustats();
+            cpustats.interrupts = field("interrupts");
+            cpustats.ctx_switches = field("context_switches");
+            cpustats.processes = field("processes_forked");
+        }
+        "processes" => {
+            let cpustats = snapshot.cpustats();
+            cpustats.procs_running = field("running");
+            cpustats.procs_blocked = field("blocked");
+        }
+        other => return Err(format!("unmapped measurement '{}'", other)),
+    }
+
+    Ok(())
+}
+
+/// Split `input` on `sep`, ignoring escaped separators and (if `quotes`)
+/// the separators found inside double quoted strings.
+fn split_unescaped(input: &str, sep: u8, quotes: bool) -> Vec<&str> {
+    let bytes = input.as_bytes();
+    let mut parts = Vec::new();
+    let (mut start, mut idx, mut quoted) = (0, 0, false);
+
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'\\' => idx += 1,
+            b'"' if quotes => quoted = !quoted,
+            b if b == sep && !quoted => {
+                parts.push(&input[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+        idx += 1;
+    }
+    parts.push(&input[start.min(input.len())..]);
+
+    parts
+}
+
+/// Remove the backslashes used to escape the special characters
+fn unescape(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(',' | '=' | ' ' | '"' | '\\')) => out.push(chars.next().unwrap()),
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+fn split_pair(pair: &str) -> Result<(String, &str), String> {
+    match split_unescaped(pair, b'=', true).as_slice() {
+        [key, value] if !key.is_empty() && !value.is_empty() => Ok((unescape(key), *value)),
+        _ => Err(format!("invalid key=value pair '{}'", pair)),
+    }
+}
+
+fn parse_field_value(raw: &str) -> Result<FieldValue, String> {
+    let invalid = || format!("invalid field value '{}'", raw);
+
+    if let Some(inner) = raw.strip_prefix('"') {
+        let inner = inner.strip_suffix('"').ok_or_else(invalid)?;
+        return Ok(FieldValue::Str(unescape(inner)));
+    }
+
+    match raw {
+        "t" | "T" | "true" | "True" | "TRUE" => Ok(FieldValue::Bool(true)),
+        "f" | "F" | "false" | "False" | "FALSE" => Ok(FieldValue::Bool(false)),
+        _ => {
+            if let Some(int) = raw.strip_suffix('i') {
+                int.parse().map(FieldValue::Int).map_err(|_| invalid())
+            } else if let Some(uint) = raw.strip_suffix('u') {
+                uint.parse().map(FieldValue::UInt).map_err(|_| invalid())
+            } else {
+                raw.parse().map(FieldValue::Float).map_err(|_| invalid())
+            }
+        }
+    }
+}
+
+/// Parse a single line of the line protocol
+/// `measurement[,tag=value...] field=value[,field=value...] [timestamp]`
+fn parse_line(raw: &str) -> Result<Line, String> {
+    let sections = split_unescaped(raw, b' ', true);
+    let (series, fields, timestamp) = match sections.as_slice() {
+        [series, fields] => (*series, *fields, None),
+        [series, fields, timestamp] => (*series, *fields, Some(*timestamp)),
+        _ => return Err(String::from("expected measurement, fields and timestamp")),
+    };
+
+    let mut series = split_unescaped(series, b',', false).into_iter();
+    let measurement = unescape(series.next().unwrap_or_default());
+    if measurement.is_empty() {
+        return Err(String::from("missing measurement"));
+    }
+
+    let mut tags = BTreeMap::new();
+    for pair in series {
+        let (key, value) = split_pair(pair)?;
+        tags.insert(key, unescape(value));
+    }
+
+    let mut parsed = BTreeMap::new();
+    for pair in split_unescaped(fields, b',', true) {
+        let (key, value) = split_pair(pair)?;
+        parsed.insert(key, parse_field_value(value)?);
+    }
+
+    let timestamp = match timestamp {
+        Some(ts) => Some(
+            ts.parse::<i64>()
+                .map_err(|_| format!("invalid timestamp '{}'", ts))?,
+        ),
+        None => None,
+    };
+
+    Ok(Line {
+        measurement,
+        tags,
+        fields: parsed,
+        timestamp,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_line_escapes() {
+        let line = parse_line(
+            r#"disk\ io,path=/mnt/my\ disk,fs=a\,b,k\=ey=v used=12i,label="a, b=c" 1700000000000000000"#,
+        )
+        .unwrap();
+
+        assert_eq!(line.measurement, "disk io");
+        assert_eq!(line.tag("path"), "/mnt/my disk");
+        assert_eq!(line.tag("fs"), "a,b");
+        assert_eq!(line.tag("k=ey"), "v");
+        assert_eq!(
+            line.fields["label"],
+            FieldValue::Str(String::from("a, b=c"))
+        );
+        assert_eq!(line.timestamp, Some(1_700_000_000_000_000_000));
+    }
+
+    #[test]
+    fn parse_line_field_types() {
+        let line =
+            parse_line(r#"cpu int=-12i,uint=3u,float=42.5,exp=1e3,yes=t,no=FALSE,str="x\"y""#)
+                .unwrap();
+
+        assert_eq!(line.fields["int"], FieldValue::Int(-12));
+        assert_eq!(line.fields["uint"], FieldValue::UInt(3));
+        assert_eq!(line.fields["float"], FieldValue::Float(42.5));
+        assert_eq!(line.fields["exp"], FieldValue::Float(1000.0));
+        assert_eq!(line.fields["yes"], FieldValue::Bool(true));
+        assert_eq!(line.fields["no"], FieldValue::Bool(false));
+        assert_eq!(line.fields["str"], FieldValue::Str(String::from("x\"y")));
+        assert_eq!(line.field("int"), Some(-12));
+        assert_eq!(line.field("str"), None);
+        assert_eq!(line.timestamp, None);
+    }
+
+    #[test]
+    fn parse_line_errors() {
+        assert!(parse_line("cpu").is_err());
+        assert!(parse_line(",host=a usage=1").is_err());
+        assert!(parse_line("cpu usage=abc").is_err());
+        assert!(parse_line("cpu usage=12x").is_err());
+        assert!(parse_line("cpu usage=-1u").is_err());
+        assert!(parse_line(r#"cpu label="open"#).is_err());
+        assert!(parse_line("cpu,host usage=1").is_err());
+        assert!(parse_line("cpu usage=1 yesterday").is_err());
+    }
+}
@@ -0,0 +1,21 @@
+//! Receivers accept metrics in the format of other monitoring
+//! tools and map them onto our own tables. Just like POST /api/hosts
+//! they're protected by the SptkValidator.
+use chrono::NaiveDateTime;
+
+pub mod influx;
+pub mod otlp;
+pub mod prom;
+
+/// Number of clock ticks per second used by the kernel to report
+/// the cpu times (and thus by the agent to fill cputimes).
+const USER_HZ: f64 = 100.0;
+
+/// Filesystems which don't represent a real disk
+const PSEUDO_FS: [&str; 9] = [
+    "tmpfs", "devtmpfs", "overlay", "squashfs", "ramfs", "proc", "sysfs", "nsfs", "autofs",
+];
+
+fn timestamp_from_millis(millis: i64) -> Option<NaiveDateTime> {
+    chrono::DateTime::from_timestamp_millis(millis).map(|date| date.naive_utc())
+}
@@ -0,0 +1,463 @@
+//! OpenTelemetry OTLP/HTTP metrics receiver.
+//! Both the protobuf and the JSON encodings are accepted, the types
+//! below derive both prost::Message and Deserialize for that purpose.
+//! Data points following the system.* host metrics semantic conventions
+//! are mapped onto our own tables, the others are kept as custom_metrics.
+//!
+//! If a resource carries a `host.id` attribute, it must be equal to the
+//! uuid of the host, otherwise its data points are rejected (and reported
+//! as such in the partial_success of the response).
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::NaiveDateTime;
+use prost::Message;
+use serde::{Deserialize, Deserializer, Serialize};
+use sproot::apierrors::ApiError;
+use sproot::models::Specific;
+use std::collections::BTreeMap;
+
+use crate::ingest::{busy, Snapshot, STAGING};
+
+use super::{PSEUDO_FS, USER_HZ};
+
+const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+
+#[derive(Clone, PartialEq, Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExportMetricsServiceRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub resource_metrics: Vec<ResourceMetrics>,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportMetricsServiceResponse {
+    #[prost(message, optional, tag = "1")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partial_success: Option<ExportMetricsPartialSuccess>,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportMetricsPartialSuccess {
+    #[prost(int64, tag = "1")]
+    pub rejected_data_points: i64,
+    #[prost(string, tag = "2")]
+    pub error_message: String,
+}
+
+#[derive(Clone, PartialEq, Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ResourceMetrics {
+    #[prost(message, optional, tag = "1")]
+    pub resource: Option<Resource>,
+    #[prost(message, repeated, tag = "2")]
+    pub scope_metrics: Vec<ScopeMetrics>,
+}
+
+#[derive(Clone, PartialEq, Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Resource {
+    #[prost(message, repeated, tag = "1")]
+    pub attributes: Vec<KeyValue>,
+}
+
+#[derive(Clone, PartialEq, Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScopeMetrics {
+    #[prost(message, repeated, tag = "2")]
+    pub metrics: Vec<Metric>,
+}
+
+#[derive(Clone, PartialEq, Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Metric {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(oneof = "MetricData", tags = "5, 7")]
+    #[serde(flatten)]
+    pub data: Option<MetricData>,
+}
+
+/// Only the Gauge and Sum are supported, the histograms and summaries
+/// are ignored as we have no way to store them.
+#[derive(Clone, PartialEq, prost::Oneof, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MetricData {
+    #[prost(message, tag = "5")]
+    Gauge(NumberDataPoints),
+    #[prost(message, tag = "7")]
+    Sum(NumberDataPoints),
+}
+
+#[derive(Clone, PartialEq, Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NumberDataPoints {
+    #[prost(message, repeated, tag = "1")]
+    pub data_points: Vec<NumberDataPoint>,
+}
+
+#[derive(Clone, PartialEq, Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NumberDataPoint {
+    #[prost(message, repeated, tag = "7")]
+    pub attributes: Vec<KeyValue>,
+    #[prost(fixed64, tag = "3")]
+    #[serde(deserialize_with = "de_u64")]
+    pub time_unix_nano: u64,
+    #[prost(oneof = "NumberValue", tags = "4, 6")]
+    #[serde(flatten)]
+    pub value: Option<NumberValue>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NumberValue {
+    #[prost(double, tag = "4")]
+    AsDouble(f64),
+    #[prost(sfixed64, tag = "6")]
+    #[serde(deserialize_with = "de_i64")]
+    AsInt(i64),
+}
+
+#[derive(Clone, PartialEq, Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct KeyValue {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(message, optional, tag = "2")]
+    pub value: Option<AnyValue>,
+}
+
+#[derive(Clone, PartialEq, Message, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AnyValue {
+    #[prost(oneof = "Value", tags = "1, 2, 3, 4")]
+    #[serde(flatten)]
+    pub value: Option<Value>,
+}
+
+/// Arrays, kvlists and bytes attributes are not supported
+#[derive(Clone, PartialEq, prost::Oneof, Deserialize)]
+pub enum Value {
+    #[prost(string, tag = "1")]
+    #[serde(rename = "stringValue")]
+    String(String),
+    #[prost(bool, tag = "2")]
+    #[serde(rename = "boolValue")]
+    Bool(bool),
+    #[prost(int64, tag = "3")]
+    #[serde(rename = "intValue", deserialize_with = "de_i64")]
+    Int(i64),
+    #[prost(double, tag = "4")]
+    #[serde(rename = "doubleValue")]
+    Double(f64),
+}
+
+impl Value {
+    fn to_text(&self) -> String {
+        match self {
+            Value::String(val) => val.to_owned(),
+            Value::Bool(val) => val.to_string(),
+            Value::Int(val) => val.to_string(),
+            Value::Double(val) => val.to_string(),
+        }
+    }
+}
+
+/// The JSON encoding of OTLP represents the 64 bits integers as strings
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StrOrNum<T> {
+    Str(String),
+    Num(T),
+}
+
+fn de_u64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    match StrOrNum::<u64>::deserialize(deserializer)? {
+        StrOrNum::Str(val) => val.parse().map_err(serde::de::Error::custom),
+        StrOrNum::Num(val) => Ok(val),
+    }
+}
+
+fn de_i64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    match StrOrNum::<i64>::deserialize(deserializer)? {
+        StrOrNum::Str(val) => val.parse().map_err(serde::de::Error::custom),
+        StrOrNum::Num(val) => Ok(val),
+    }
+}
+
+fn attributes_map(attributes: &[KeyValue]) -> BTreeMap<String, String> {
+    attributes
+        .iter()
+        .filter_map(|kv| {
+            let value = kv.value.as_ref()?.value.as_ref()?;
+            Some((kv.key.to_owned(), value.to_text()))
+        })
+        .collect()
+}
+
+/// POST /api/otlp/v1/metrics
+/// Save the data points of an OTLP export request under the host uuid
+pub async fn otlp_metrics(
+    req: HttpRequest,
+    info: web::Query<Specific>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route POST /api/otlp/v1/metrics");
+
+    let is_protobuf = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|val| val.to_str().ok())
+        .is_some_and(|val| val.starts_with(PROTOBUF_CONTENT_TYPE));
+
+    let request = if is_protobuf {
+        ExportMetricsServiceRequest::decode(body).map_err(|err| {
+            ApiError::InvalidRequestError(Some(format!("invalid protobuf payload: {}", err)))
+        })?
+    } else {
+        serde_json::from_slice::<ExportMetricsServiceRequest>(&body).map_err(|err| {
+            ApiError::InvalidRequestError(Some(format!("invalid json payload: {}", err)))
+        })?
+    };
+
+    let mut rejected = 0;
+    let staged = STAGING.with(&info.uuid, |stage| {
+        for resource_metrics in request.resource_metrics {
+            let resource = attributes_map(
+                resource_metrics
+                    .resource
+                    .as_ref()
+                    .map_or(&[], |res| res.attributes.as_slice()),
+            );
+
+            let points = resource_metrics
+                .scope_metrics
+                .into_iter()
+                .flat_map(|scope| scope.metrics)
+                .filter_map(|metric| match metric.data {
+                    Some(MetricData::Gauge(data)) | Some(MetricData::Sum(data)) => {
+                        Some((metric.name, data.data_points))
+                    }
+                    None => None,
+                });
+
+            if resource.get("host.id").is_some_and(|id| id != &info.uuid) {
+                rejected += points.map(|(_, points)| points.len() as i64).sum::<i64>();
+                continue;
+            }
+
+            for (name, points) in points {
+                for point in points {
+                    let value = match point.value {
+                        Some(NumberValue::AsDouble(val)) => val,
+                        Some(NumberValue::AsInt(val)) => val as f64,
+                        None => continue,
+                    };
+                    let Some(created_at) = timestamp_from_nanos(point.time_unix_nano) else {
+                        continue;
+                    };
+                    let attributes = attributes_map(&point.attributes);
+
+                    let snapshot = stage.at(created_at);
+                    fill_host(snapshot, &resource);
+                    if !map_host_metrics(snapshot, &name, &attributes, value) {
+                        snapshot.custom(&name, serde_json::json!(attributes), value);
+                    }
+                }
+            }
+        }
+    });
+
+    if !staged {
+        return Ok(busy());
+    }
+
+    let response = ExportMetricsServiceResponse {
+        partial_success: (rejected > 0).then(|| ExportMetricsPartialSuccess {
+            rejected_data_points: rejected,
+            error_message: String::from("host.id does not match the uuid of the host"),
+        }),
+    };
+
+    if is_protobuf {
+        Ok(HttpResponse::Ok()
+            .content_type(PROTOBUF_CONTENT_TYPE)
+            .body(response.encode_to_vec()))
+    } else {
+        Ok(HttpResponse::Ok().json(response))
+    }
+}
+
+fn timestamp_from_nanos(nanos: u64) -> Option<NaiveDateTime> {
+    let nanos = i64::try_from(nanos).ok()?;
+    Some(chrono::DateTime::from_timestamp_nanos(nanos).naive_utc())
+}
+
+/// Use the resource's attributes to fill the hosts row
+fn fill_host(snapshot: &mut Snapshot, resource: &BTreeMap<String, String>) {
+    if let Some(name) = resource.get("host.name") {
+        snapshot.host.hostname = Some(name.to_owned());
+    }
+    if let Some(os) = resource.get("os.type") {
+        snapshot.host.system = Some(os.to_owned());
+    }
+    if let Some(version) = resource
+        .get("os.version")
+        .or_else(|| resource.get("os.description"))
+    {
+        snapshot.host.os_version = Some(version.to_owned());
+    }
+}
+
+/// Map a system.* data point onto the Snapshot, return false if the
+/// metric is not one we know about.
+fn map_host_metrics(
+    snapshot: &mut Snapshot,
+    name: &str,
+    attributes: &BTreeMap<String, String>,
+    value: f64,
+) -> bool {
+    let attr = |key: &str| attributes.get(key).map(String::as_str).unwrap_or_default();
+    let int = value as i64;
+
+    match (name, attr("direction")) {
+        ("system.cpu.time", _) => {
+            let column = match attr("state") {
+                "interrupt" => "irq",
+                "wait" => "iowait",
+                state => state,
+            };
+            snapshot.cpu_time(attr("cpu"), column, (value * USER_HZ) as i64);
+        }
+        ("system.cpu.load_average.1m", _) => snapshot.loadavg().one = value,
+        ("system.cpu.load_average.5m", _) => snapshot.loadavg().five = value,
+        ("system.cpu.load_average.15m", _) => snapshot.loadavg().fifteen = value,
+        ("system.processes.count", _) => match attr("status") {
+            "running" => snapshot.cpustats().procs_running = int,
+            "blocked" => snapshot.cpustats().procs_blocked = int,
+            _ => return false,
+        },
+        ("system.processes.created", _) => snapshot.cpustats().processes = int,
+        ("system.memory.usage", _) => {
+            snapshot.memory_usage(attr("state"), int);
+            let memory = snapshot.memory();
+            match attr("state") {
+                "used" => memory.used = int,
+                "free" => memory.free = int,
+                "buffered" => memory.buffers = int,
+                "cached" => memory.cached = int,
+                _ => {}
+            }
+        }
+        ("system.paging.usage", _) => {
+            snapshot.swap_usage(attr("device"), attr("state"), int);
+        }
+        ("system.filesystem.usage", _) => {
+            if PSEUDO_FS.contains(&attr("type")) {
+                return false;
+            }
+            let (device, mount_point) = (attr("device"), attr("mountpoint"));
+            snapshot.disk_usage(device, mount_point, attr("state"), int);
+            if attr("state") == "free" {
+                snapshot.disk(device, mount_point).avail_space = int;
+            }
+        }
+        ("system.disk.io", "read") => snapshot.ioblock(attr("device")).read_bytes = int,
+        ("system.disk.io", "write") => snapshot.ioblock(attr("device")).write_bytes = int,
+        ("system.disk.operations", "read") => snapshot.ioblock(attr("device")).read_count = int,
+        ("system.disk.operations", "write") => snapshot.ioblock(attr("device")).write_count = int,
+        ("system.disk.io_time", _) => {
+            snapshot.ioblock(attr("device")).busy_time = (value * 1000.0) as i64
+        }
+        ("system.network.io", "receive") => snapshot.ionet(attr("device")).rx_bytes = int,
+        ("system.network.io", "transmit") => snapshot.ionet(attr("device")).tx_bytes = int,
+        ("system.network.packets", "receive") => snapshot.ionet(attr("device")).rx_packets = int,
+        ("system.network.packets", "transmit") => snapshot.ionet(attr("device")).tx_packets = int,
+        ("system.network.errors", "receive") => snapshot.ionet(attr("device")).rx_errs = int,
+        ("system.network.errors", "transmit") => snapshot.ionet(attr("device")).tx_errs = int,
+        ("system.network.dropped", "receive") => snapshot.ionet(attr("device")).rx_drop = int,
+        ("system.network.dropped", "transmit") => snapshot.ionet(attr("device")).tx_drop = int,
+        ("system.uptime", _) => snapshot.host.uptime = Some(int),
+        _ => return false,
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Rows;
+
+    /// A data point: (name, attributes, value)
+    type Point = (&'static str, Vec<(&'static str, &'static str)>, f64);
+
+    /// The system.* points of an export
+    fn export() -> Vec<Point> {
+        vec![
+            ("system.memory.usage", vec![("state", "used")], 600.0),
+            ("system.memory.usage", vec![("state", "free")], 300.0),
+            ("system.memory.usage", vec![("state", "cached")], 100.0),
+            (
+                "system.paging.usage",
+                vec![("device", "sda2"), ("state", "used")],
+                10.0,
+            ),
+            (
+                "system.paging.usage",
+                vec![("device", "sda2"), ("state", "free")],
+                90.0,
+            ),
+            (
+                "system.paging.usage",
+                vec![("device", "sdb1"), ("state", "free")],
+                50.0,
+            ),
+            (
+                "system.filesystem.usage",
+                vec![("device", "sda1"), ("mountpoint", "/"), ("state", "used")],
+                700.0,
+            ),
+            (
+                "system.filesystem.usage",
+                vec![("device", "sda1"), ("mountpoint", "/"), ("state", "free")],
+                300.0,
+            ),
+        ]
+    }
+
+    #[test]
+    fn usage_of_a_retried_export() {
+        let created_at = chrono::DateTime::from_timestamp(1_700_000_000, 0)
+            .unwrap()
+            .naive_utc();
+        let mut snapshot = Snapshot::new("host", created_at);
+
+        // The exporter retried: the same points are received twice
+        for _ in 0..2 {
+            for (name, attributes, value) in export() {
+                let attributes = attributes
+                    .into_iter()
+                    .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                    .collect();
+                assert!(map_host_metrics(&mut snapshot, name, &attributes, value));
+            }
+        }
+
+        let mut rows = Rows::default();
+        snapshot.into_rows(&mut rows);
+
+        let memory = &rows.memory[0];
+        assert_eq!(
+            (memory.total, memory.used, memory.free, memory.cached),
+            (1000, 600, 300, 100)
+        );
+        let swap = &rows.swap[0];
+        assert_eq!((swap.total, swap.used, swap.free), (150, 10, 140));
+        let disk = &rows.disks[0];
+        assert_eq!((disk.total_space, disk.avail_space), (1000, 300));
+        assert_eq!(rows.disks.len(), 1);
+    }
+}
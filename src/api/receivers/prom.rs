@@ -0,0 +1,177 @@
+//! Prometheus remote_write receiver.
+//! The payload is a snappy compressed protobuf WriteRequest. Series
+//! coming from node_exporter are mapped onto our own tables, all the
+//! other ones are kept as custom_metrics.
+use actix_web::{web, HttpResponse};
+use prost::Message;
+use sproot::apierrors::ApiError;
+use sproot::models::Specific;
+use std::collections::BTreeMap;
+
+use crate::ingest::{busy, Snapshot, STAGING};
+
+use super::{timestamp_from_millis, PSEUDO_FS, USER_HZ};
+
+#[derive(Clone, PartialEq, Message)]
+pub struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    pub labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Label {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Sample {
+    #[prost(double, tag = "1")]
+    pub value: f64,
+    #[prost(int64, tag = "2")]
+    pub timestamp: i64,
+}
+
+/// POST /api/prom/write
+/// Save the samples of a Prometheus remote_write request under the host uuid
+pub async fn prom_write(
+    info: web::Query<Specific>,
+    body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+    trace!("Route POST /api/prom/write");
+
+    let raw = snap::raw::Decoder::new()
+        .decompress_vec(&body)
+        .map_err(|err| {
+            ApiError::InvalidRequestError(Some(format!("invalid snappy payload: {}", err)))
+        })?;
+    let request = WriteRequest::decode(raw.as_slice()).map_err(|err| {
+        ApiError::InvalidRequestError(Some(format!("invalid WriteRequest: {}", err)))
+    })?;
+
+    let staged = STAGING.with(&info.uuid, |stage| {
+        for series in request.timeseries {
+            let mut name = String::new();
+            let mut labels = BTreeMap::new();
+            for label in series.labels {
+                if label.name == "__name__" {
+                    name = label.value;
+                } else {
+                    labels.insert(label.name, label.value);
+                }
+            }
+
+            for sample in series.samples {
+                // NaN are used by Prometheus as staleness markers
+                if !sample.value.is_finite() {
+                    continue;
+                }
+                let Some(created_at) = timestamp_from_millis(sample.timestamp) else {
+                    continue;
+                };
+
+                let snapshot = stage.at(created_at);
+                if !map_node_exporter(snapshot, &name, &labels, sample.value) {
+                    snapshot.custom(&name, serde_json::json!(labels), sample.value);
+                }
+            }
+        }
+    });
+
+    if !staged {
+        return Ok(busy());
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Map a node_exporter sample onto the Snapshot, return false if the
+/// series is not one we know about.
+fn map_node_exporter(
+    snapshot: &mut Snapshot,
+    name: &str,
+    labels: &BTreeMap<String, String>,
+    value: f64,
+) -> bool {
+    let label = |key: &str| labels.get(key).map(String::as_str).unwrap_or_default();
+    let int = value as i64;
+
+    match name {
+        "node_cpu_seconds_total" => {
+            let ticks = (value * USER_HZ) as i64;
+            snapshot.cpu_time(label("cpu"), label("mode"), ticks);
+        }
+        "node_cpu_guest_seconds_total" => {
+            let ticks = (value * USER_HZ) as i64;
+            match label("mode") {
+                "user" => snapshot.cpu_time(label("cpu"), "guest", ticks),
+                "nice" => snapshot.cpu_time(label("cpu"), "guest_nice", ticks),
+                _ => return false,
+            }
+        }
+        "node_intr_total" => snapshot.cpustats().interrupts = int,
+        "node_context_switches_total" => snapshot.cpustats().ctx_switches = int,
+        "node_forks_total" => snapshot.cpustats().processes = int,
+        "node_procs_running" => snapshot.cpustats().procs_running = int,
+        "node_procs_blocked" => snapshot.cpustats().procs_blocked = int,
+        "node_softirqs_total" => snapshot.soft_interrupts(label("vector"), int),
+        "node_load1" => snapshot.loadavg().one = value,
+        "node_load5" => snapshot.loadavg().five = value,
+        "node_load15" => snapshot.loadavg().fifteen = value,
+        "node_memory_MemTotal_bytes" => snapshot.memory().total = int,
+        "node_memory_MemFree_bytes" => snapshot.memory().free = int,
+        "node_memory_Shmem_bytes" => snapshot.memory().shared = int,
+        "node_memory_Buffers_bytes" => snapshot.memory().buffers = int,
+        "node_memory_Cached_bytes" => snapshot.memory().cached = int,
+        "node_memory_SwapTotal_bytes" => snapshot.swap().total = int,
+        "node_memory_SwapFree_bytes" => snapshot.swap().free = int,
+        "node_filesystem_size_bytes" | "node_filesystem_avail_bytes" => {
+            if PSEUDO_FS.contains(&label("fstype")) {
+                return false;
+            }
+            let disk = snapshot.disk(label("device"), label("mountpoint"));
+            if name == "node_filesystem_size_bytes" {
+                disk.total_space = int;
+            } else {
+                disk.avail_space = int;
+            }
+        }
+        "node_disk_reads_completed_total" => snapshot.ioblock(label("device")).read_count = int,
+        "node_disk_read_bytes_total" => snapshot.ioblock(label("device")).read_bytes = int,
+        "node_disk_writes_completed_total" => snapshot.ioblock(label("device")).write_count = int,
+        "node_disk_written_bytes_total" => snapshot.ioblock(label("device")).write_bytes = int,
+        "node_disk_io_time_seconds_total" => {
+            snapshot.ioblock(label("device")).busy_time = (value * 1000.0) as i64
+        }
+        "node_network_receive_bytes_total" => snapshot.ionet(label("device")).rx_bytes = int,
+        "node_network_receive_packets_total" => snapshot.ionet(label("device")).rx_packets = int,
+        "node_network_receive_errs_total" => snapshot.ionet(label("device")).rx_errs = int,
+        "node_network_receive_drop_total" => snapshot.ionet(label("device")).rx_drop = int,
+        "node_network_transmit_bytes_total" => snapshot.ionet(label("device")).tx_bytes = int,
+        "node_network_transmit_packets_total" => snapshot.ionet(label("device")).tx_packets = int,
+        "node_network_transmit_errs_total" => snapshot.ionet(label("device")).tx_errs = int,
+        "node_network_transmit_drop_total" => snapshot.ionet(label("device")).tx_drop = int,
+        "node_uname_info" => {
+            snapshot.host.system = Some(label("sysname").to_owned());
+            snapshot.host.os_version = Some(label("release").to_owned());
+            snapshot.host.hostname = Some(label("nodename").to_owned());
+        }
+        "node_boot_time_seconds" => {
+            let now = snapshot.host.created_at.and_utc().timestamp();
+            snapshot.host.uptime = Some(now - int);
+        }
+        _ => return false,
+    }
+
+    true
+}
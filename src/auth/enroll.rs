@@ -0,0 +1,123 @@
+//! Auto-enrollment: with auto_enroll, an api key not bound to any host yet
+//! is bound to the host presenting it on its first ingest, instead of the
+//! agent having to do it through the auth SSOT server (412).
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use diesel::sql_types::{BigInt, Text};
+use diesel::{sql_query, QueryableByName, RunQueryDsl};
+use sproot::apierrors::ApiError;
+use sproot::ConnType;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::models::NewEnrollment;
+use crate::{AUTHPOOL, CONFIG, METRICSPOOL};
+
+/// Size of the host_uuid columns
+const MAX_HOST_UUID_LEN: usize = 48;
+/// Attempts of the binding (on serialization failures) and of its audit
+const MAX_ATTEMPTS: usize = 3;
+/// Delay before retrying the audit, times the number of attempts
+const AUDIT_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+#[derive(QueryableByName)]
+struct BoundKey {
+    #[diesel(sql_type = BigInt)]
+    id: i64,
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    customer_id: Uuid,
+}
+
+/// Bind the api key to the host, provided the key belongs to this berta,
+/// is still unbound and no other key of this berta is bound to the host.
+/// Return whether the key is bound to the host.
+pub fn enroll(key: &str, uuid: &str, remote_addr: Option<String>) -> Result<bool, ApiError> {
+    if uuid.is_empty() || uuid.len() > MAX_HOST_UUID_LEN {
+        return Ok(false);
+    }
+
+    let mut conn = AUTHPOOL.get()?;
+    let mut attempt = 1;
+    let bound = loop {
+        match bind(&mut conn, key, uuid) {
+            Err(DieselError::DatabaseError(DatabaseErrorKind::SerializationFailure, _))
+                if attempt < MAX_ATTEMPTS =>
+            {
+                debug!("auto_enroll: serialization failure for {}, retrying", uuid);
+                attempt += 1;
+            }
+            res => break res?,
+        }
+    };
+
+    let Some(bound) = bound else {
+        // A concurrent first ingest of the host may have bound the key already
+        return Ok(is_bound(&mut conn, key, uuid)?);
+    };
+    info!("auto_enroll: api key {} bound to {}", bound.id, uuid);
+
+    // The audit lives in the metrics database, the binding is undone
+    // if it can't be recorded so that no binding goes unaudited.
+    let enrollment = NewEnrollment {
+        host_uuid: uuid.to_owned(),
+        key_id: bound.id,
+        customer_id: bound.customer_id,
+        berta: CONFIG.berta_name.to_owned(),
+        remote_addr,
+    };
+    let mut attempt = 1;
+    while let Err(err) = METRICSPOOL
+        .get()
+        .map_err(ApiError::from)
+        .and_then(|mut conn| enrollment.insert(&mut conn))
+    {
+        if attempt < MAX_ATTEMPTS {
+            std::thread::sleep(AUDIT_RETRY_DELAY * attempt as u32);
+            attempt += 1;
+            continue;
+        }
+
+        error!(
+            "auto_enroll: cannot record the enrollment of {}, unbinding: {}",
+            uuid, err
+        );
+        sql_query("UPDATE apikeys SET host_uuid = NULL WHERE id = $1 AND host_uuid = $2")
+            .bind::<BigInt, _>(bound.id)
+            .bind::<Text, _>(uuid)
+            .execute(&mut conn)?;
+        return Err(err);
+    }
+
+    Ok(true)
+}
+
+/// Bind the key in a serializable transaction, so two keys can't be
+/// bound to the same host concurrently
+fn bind(conn: &mut ConnType, key: &str, uuid: &str) -> Result<Option<BoundKey>, DieselError> {
+    conn.build_transaction().serializable().run(|conn| {
+        Ok(sql_query(
+            "UPDATE apikeys SET host_uuid = $1 \
+            WHERE key = $2 AND berta = $3 AND host_uuid IS NULL \
+            AND NOT EXISTS (SELECT 1 FROM apikeys WHERE host_uuid = $1 AND berta = $3) \
+            RETURNING id, customer_id",
+        )
+        .bind::<Text, _>(uuid)
+        .bind::<Text, _>(key)
+        .bind::<Text, _>(&CONFIG.berta_name)
+        .load::<BoundKey>(conn)?
+        .into_iter()
+        .next())
+    })
+}
+
+/// Whether the key of this berta is bound to the host
+fn is_bound(conn: &mut ConnType, key: &str, uuid: &str) -> Result<bool, DieselError> {
+    let keys: Vec<BoundKey> = sql_query(
+        "SELECT id, customer_id FROM apikeys WHERE key = $1 AND berta = $2 AND host_uuid = $3",
+    )
+    .bind::<Text, _>(key)
+    .bind::<Text, _>(&CONFIG.berta_name)
+    .bind::<Text, _>(uuid)
+    .load(conn)?;
+
+    Ok(!keys.is_empty())
+}
@@ -0,0 +1,171 @@
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{self, ServiceRequest, ServiceResponse};
+use actix_web::dev::{Service, Transform};
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::{web, Error, HttpResponse};
+use diesel::{OptionalExtension, QueryDsl, RunQueryDsl};
+use futures_util::future::LocalBoxFuture;
+use sproot::apierrors::ApiError;
+use sproot::models::Specific;
+
+use crate::models::schema::hosts;
+use crate::utils::stats::STATS;
+use crate::{CONFIG, METRICSPOOL};
+
+use super::{INGESTBUCKETS_CACHE, SYNCINTERVAL_CACHE};
+
+/// Token bucket of a host: refilled by one token every sync_interval
+/// seconds and holding at most 1 + CONFIG.ingest_burst tokens.
+#[derive(Debug)]
+pub struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    pub(crate) fn new(capacity: f64) -> Self {
+        Self {
+            tokens: capacity,
+            last: Instant::now(),
+        }
+    }
+
+    /// Take a token, or return the number of seconds to wait for one
+    pub(crate) fn take(&mut self, interval: f64, capacity: f64) -> Result<(), u64> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed / interval).min(capacity);
+        self.last = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - self.tokens) * interval).ceil().max(1.0) as u64)
+        }
+    }
+}
+
+/// Get the declared sync_interval of the host from the database and
+/// refresh the cache. Unknown hosts (first ingest) use the default of
+/// the column.
+pub async fn fetch_sync_interval(uuid: &str) -> Result<i64, ApiError> {
+    let owned = uuid.to_owned();
+    let interval = web::block(move || {
+        hosts::table
+            .find(&owned)
+            .select(hosts::sync_interval)
+            .first::<i64>(&mut METRICSPOOL.get()?)
+            .optional()
+            .map_err(ApiError::from)
+    })
+    .await??
+    .unwrap_or(1);
+
+    SYNCINTERVAL_CACHE.insert(uuid.to_owned(), interval);
+    Ok(interval)
+}
+
+/// Get the sync_interval of the host, from the cache if possible
+pub async fn sync_interval(uuid: &str) -> Result<i64, ApiError> {
+    match SYNCINTERVAL_CACHE.get(uuid) {
+        Some(interval) => Ok(interval),
+        None => fetch_sync_interval(uuid).await,
+    }
+}
+
+/// Take a token from the bucket of the host, or return the number of
+/// seconds to wait before one is available.
+pub fn take_token(uuid: &str, interval: i64) -> Result<(), u64> {
+    let capacity = 1.0 + CONFIG.ingest_burst as f64;
+    let bucket = INGESTBUCKETS_CACHE.get_with(uuid.to_owned(), || {
+        Arc::new(Mutex::new(Bucket::new(capacity)))
+    });
+
+    let res = bucket
+        .lock()
+        .unwrap()
+        .take(interval.max(1) as f64, capacity);
+    if res.is_err() {
+        STATS.rate_limited();
+    }
+    res
+}
+
+pub struct IngestLimiter;
+
+impl<S: 'static, B> Transform<S, ServiceRequest> for IngestLimiter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = IngestLimiterMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(IngestLimiterMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+pub struct IngestLimiterMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for IngestLimiterMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    dev::forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let (request, pl) = request.into_parts();
+        let svc = self.service.clone();
+
+        // Construct the Specific (get the uuid) from the query_string
+        let info = match web::Query::<Specific>::from_query(request.query_string()) {
+            Ok(info) => info.into_inner(),
+            Err(err) => {
+                debug!("IngestLimiter: No Specific query found ({})", err);
+                let response = HttpResponse::BadRequest().finish().map_into_right_body();
+                return Box::pin(async { Ok(ServiceResponse::new(request, response)) });
+            }
+        };
+
+        Box::pin(async move {
+            let interval = sync_interval(&info.uuid).await?;
+
+            match take_token(&info.uuid, interval) {
+                Ok(()) => {
+                    let res = svc.call(ServiceRequest::from_parts(request, pl));
+                    res.await.map(ServiceResponse::map_into_left_body)
+                }
+                Err(retry_after) => {
+                    debug!("IngestLimiter: {} is over its sync_interval", info.uuid);
+                    let response = HttpResponse::TooManyRequests()
+                        .insert_header((RETRY_AFTER, retry_after.to_string()))
+                        .finish()
+                        .map_into_right_body();
+                    Ok(ServiceResponse::new(request, response))
+                }
+            }
+        })
+    }
+}
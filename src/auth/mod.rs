@@ -1,12 +1,18 @@
 use actix_web::{dev, web};
 use moka::sync::Cache;
 use once_cell::sync::Lazy;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use uuid::Uuid;
 
+use crate::CONFIG;
+
 pub mod alert_host_owned;
 pub mod alert_owned;
 pub mod check_sessions;
+pub mod enroll;
+pub mod ingest_limiter;
+pub mod signature;
 pub mod sptk_validator;
 
 static CHECKSESSIONS_CACHE: Lazy<Cache<String, Uuid>> = Lazy::new(|| {
@@ -21,6 +27,28 @@ static CHECKSPTK_CACHE: Lazy<Cache<String, String>> = Lazy::new(|| {
         .build()
 });
 
+static NONCES_CACHE: Lazy<Cache<(String, String), ()>> = Lazy::new(|| {
+    Cache::builder()
+        .time_to_live(Duration::from_secs(
+            2 * CONFIG.signature_max_age.max(0) as u64,
+        ))
+        .max_capacity(1_000_000)
+        .build()
+});
+
+static SYNCINTERVAL_CACHE: Lazy<Cache<String, i64>> = Lazy::new(|| {
+    Cache::builder()
+        .time_to_live(Duration::from_secs(60 * 5))
+        .build()
+});
+
+static INGESTBUCKETS_CACHE: Lazy<Cache<String, Arc<Mutex<ingest_limiter::Bucket>>>> =
+    Lazy::new(|| {
+        Cache::builder()
+            .time_to_idle(Duration::from_secs(60 * 60))
+            .build()
+    });
+
 fn bytes_to_payload(buf: web::Bytes) -> dev::Payload {
     let (_, mut pl) = actix_http::h1::Payload::create(true);
     pl.unread_data(buf);
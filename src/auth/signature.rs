@@ -0,0 +1,204 @@
+//! Optional signing of the agents requests, checked by SptkValidator
+//! when the SP-SIGNATURE header is present (plain SPTK otherwise).
+//!
+//! The agent sends its host uuid (?uuid=XYZ), along with:
+//! - SP-TIMESTAMP: unix time (seconds) of the request
+//! - SP-NONCE: a random string (1 to 64 chars) never reused
+//! - SP-SIGNATURE: hex encoded HMAC-SHA256 of the request
+//!
+//! The signing key is HMAC-SHA256(api key, "speculare-signature") and the
+//! signed message is "{timestamp}\n{nonce}\n{method}\n{path?query}\n"
+//! followed by the body, as sent (before decompression).
+//!
+//! Requests older (or newer) than signature_max_age are rejected, as are
+//! the nonces already seen during twice that window.
+//!
+//! The StatsD and Graphite listeners can't carry headers, their metrics
+//! are prefixed by a listener token instead: "{uuid}_{tag}" where tag is
+//! the first 16 bytes (hex encoded) of HMAC-SHA256(api key, "speculare-listener:{uuid}").
+use diesel::sql_types::Text;
+use diesel::{sql_query, QueryableByName, RunQueryDsl};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sproot::apierrors::ApiError;
+use sproot::ConnType;
+
+use crate::CONFIG;
+
+use super::NONCES_CACHE;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const MAX_NONCE_LEN: usize = 64;
+/// Length of the (hex encoded) tag of the listener tokens
+const LISTENER_TAG_LEN: usize = 32;
+
+#[derive(QueryableByName)]
+struct HostKey {
+    #[diesel(sql_type = Text)]
+    key: String,
+}
+
+/// Headers of a signed request
+pub struct Signed {
+    pub timestamp: i64,
+    pub nonce: String,
+    pub signature: Vec<u8>,
+}
+
+impl Signed {
+    /// Parse the signature headers, None if any of them is invalid
+    pub fn parse(timestamp: &str, nonce: &str, signature: &str) -> Option<Self> {
+        if nonce.is_empty() || nonce.len() > MAX_NONCE_LEN {
+            return None;
+        }
+
+        Some(Self {
+            timestamp: timestamp.parse().ok()?,
+            nonce: nonce.to_owned(),
+            signature: decode_hex(signature)?,
+        })
+    }
+
+    /// Whether the timestamp is within signature_max_age of now
+    pub fn is_fresh(&self) -> bool {
+        self.is_fresh_at(chrono::Utc::now().timestamp(), CONFIG.signature_max_age)
+    }
+
+    fn is_fresh_at(&self, now: i64, max_age: i64) -> bool {
+        (now - self.timestamp).abs() <= max_age
+    }
+
+    /// Check the signature of the request against the api key
+    pub fn verify(&self, key: &str, method: &str, path: &str, body: &[u8]) -> bool {
+        let mut mac =
+            HmacSha256::new_from_slice(&signing_key(key)).expect("HMAC can take a key of any size");
+        mac.update(
+            format!("{}\n{}\n{}\n{}\n", self.timestamp, self.nonce, method, path).as_bytes(),
+        );
+        mac.update(body);
+        mac.verify_slice(&self.signature).is_ok()
+    }
+
+    /// Remember the nonce, false if it was already used by the host
+    pub fn claim_nonce(&self, uuid: &str) -> bool {
+        NONCES_CACHE
+            .entry((uuid.to_owned(), self.nonce.to_owned()))
+            .or_insert(())
+            .is_fresh()
+    }
+}
+
+/// Derive the signing key from the api key, so the api key itself
+/// is never used as is outside of the plain SPTK scheme.
+fn signing_key(key: &str) -> Vec<u8> {
+    let mut mac =
+        HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(b"speculare-signature");
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Split a listener token into its host uuid and tag, None if malformed
+pub fn parse_listener_token(token: &str) -> Option<(&str, Vec<u8>)> {
+    let (uuid, tag) = token.split_once('_')?;
+    if uuid::Uuid::parse_str(uuid).is_err() || tag.len() != LISTENER_TAG_LEN {
+        return None;
+    }
+
+    Some((uuid, decode_hex(tag)?))
+}
+
+/// Check the tag of a listener token against the api key of its host
+pub fn verify_listener_tag(key: &str, uuid: &str, tag: &[u8]) -> bool {
+    let mut mac =
+        HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(format!("speculare-listener:{}", uuid).as_bytes());
+    mac.verify_truncated_left(tag).is_ok()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(value.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+/// Get the api key bound to the host for this berta
+pub fn fetch_host_key(conn: &mut ConnType, uuid: &str) -> Result<Option<String>, ApiError> {
+    let keys: Vec<HostKey> =
+        sql_query("SELECT key FROM apikeys WHERE host_uuid = $1 AND berta = $2 LIMIT 1")
+            .bind::<Text, _>(uuid)
+            .bind::<Text, _>(&CONFIG.berta_name)
+            .load(conn)?;
+
+    Ok(keys.into_iter().next().map(|item| item.key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "test-key";
+    const PATH: &str = "/api/hosts?uuid=u";
+    /// HMAC of "1700000000\nabc\nPOST\n/api/hosts?uuid=u\n{}" with the key derived from KEY
+    const SIGNATURE: &str = "3214db8c1134a85d3eeba1a5590884578c3a1bd3d45b655af9a7c7956fd9075e";
+
+    fn signed(signature: &str) -> Signed {
+        Signed::parse("1700000000", "abc", signature).unwrap()
+    }
+
+    #[test]
+    fn verify_signature() {
+        assert!(signed(SIGNATURE).verify(KEY, "POST", PATH, b"{}"));
+    }
+
+    #[test]
+    fn verify_tampered_request() {
+        let mut tampered = SIGNATURE.to_owned();
+        tampered.replace_range(0..2, "33");
+        assert!(!signed(&tampered).verify(KEY, "POST", PATH, b"{}"));
+
+        assert!(!signed(SIGNATURE).verify("other-key", "POST", PATH, b"{}"));
+        assert!(!signed(SIGNATURE).verify(KEY, "PUT", PATH, b"{}"));
+        assert!(!signed(SIGNATURE).verify(KEY, "POST", "/api/hosts?uuid=v", b"{}"));
+        assert!(!signed(SIGNATURE).verify(KEY, "POST", PATH, b"{ }"));
+    }
+
+    #[test]
+    fn stale_timestamp() {
+        let signed = signed(SIGNATURE);
+        assert!(signed.is_fresh_at(1_700_000_000, 300));
+        assert!(signed.is_fresh_at(1_700_000_300, 300));
+        assert!(signed.is_fresh_at(1_699_999_700, 300));
+        assert!(!signed.is_fresh_at(1_700_000_301, 300));
+        assert!(!signed.is_fresh_at(1_699_999_699, 300));
+    }
+
+    #[test]
+    fn parse_invalid_headers() {
+        assert!(Signed::parse("now", "abc", SIGNATURE).is_none());
+        assert!(Signed::parse("1700000000", "", SIGNATURE).is_none());
+        assert!(Signed::parse("1700000000", &"a".repeat(65), SIGNATURE).is_none());
+        assert!(Signed::parse("1700000000", "abc", "abc").is_none());
+        assert!(Signed::parse("1700000000", "abc", "zz").is_none());
+    }
+
+    #[test]
+    fn listener_tokens() {
+        let uuid = "8c3b6f7e-1e2a-4f4b-9a3c-2d1e0f9b7a61";
+        let token = format!("{}_ddc11a55e58e74c17c94432ae6a97ad9", uuid);
+
+        let (parsed, tag) = parse_listener_token(&token).unwrap();
+        assert_eq!(parsed, uuid);
+        assert!(verify_listener_tag(KEY, uuid, &tag));
+        assert!(!verify_listener_tag("other-key", uuid, &tag));
+
+        assert!(parse_listener_token(uuid).is_none());
+        assert!(parse_listener_token("host_ddc11a55e58e74c17c94432ae6a97ad9").is_none());
+        assert!(parse_listener_token(&format!("{}_ddc11a55", uuid)).is_none());
+    }
+}
@@ -6,12 +6,20 @@ use std::{
 use actix_web::body::EitherBody;
 use actix_web::dev::{self, ServiceRequest, ServiceResponse};
 use actix_web::dev::{Service, Transform};
-use actix_web::{web, Error, HttpResponse};
+use actix_web::http::Method;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
 use futures_util::future::LocalBoxFuture;
+use futures_util::StreamExt;
 use sproot::models::{ApiKey, Specific};
 
-use crate::{AUTHPOOL, CONFIG};
+use crate::{
+    ingest::capture_unverified,
+    utils::{payload::MAX_SIGNED_PAYLOAD_SIZE, tls::PeerIdentity},
+    AUTHPOOL, CONFIG,
+};
 
+use super::enroll::enroll;
+use super::signature::{fetch_host_key, Signed};
 use super::CHECKSPTK_CACHE;
 
 pub struct SptkValidator;
@@ -54,13 +62,53 @@ where
         let (request, pl) = request.into_parts();
         let svc = self.service.clone();
 
+        // Agents presenting a (verified) certificate are identified by it
+        if let Some(peer) = request.conn_data::<PeerIdentity>() {
+            let allowed = match web::Query::<Specific>::from_query(request.query_string()) {
+                Ok(info) => peer.hosts.contains(&info.uuid),
+                Err(_) => false,
+            };
+            if !allowed {
+                debug!("SptkValidator: certificate not issued for this host");
+                return Box::pin(reject(
+                    request,
+                    pl,
+                    HttpResponse::Unauthorized().finish(),
+                    "certificate not issued for this host",
+                ));
+            }
+
+            return Box::pin(async move {
+                let res = svc.call(ServiceRequest::from_parts(request, pl));
+                res.await.map(ServiceResponse::map_into_left_body)
+            });
+        }
+
+        // Signed requests don't carry the SPTK (see auth::signature)
+        if request.headers().contains_key("SP-SIGNATURE") {
+            return Box::pin(async move {
+                match check_signed(&request, pl).await {
+                    Ok(body) => {
+                        let pl = super::bytes_to_payload(body);
+                        let res = svc.call(ServiceRequest::from_parts(request, pl));
+                        res.await.map(ServiceResponse::map_into_left_body)
+                    }
+                    Err((response, reason, pl)) => reject(request, pl, response, reason).await,
+                }
+            });
+        }
+
         // Get the SPTK header, error if not found (400)
         let sptk = match request.headers().get("SPTK") {
             Some(sptk) => sptk.to_owned(),
             None => {
                 debug!("SptkValidator: No SPTK header found");
-                let response = HttpResponse::BadRequest().finish().map_into_right_body();
-                return Box::pin(async { Ok(ServiceResponse::new(request, response)) });
+                return Box::pin(reject(
+                    request,
+                    pl,
+                    HttpResponse::BadRequest().finish(),
+                    "no SPTK header",
+                ));
             }
         };
 
@@ -69,8 +117,12 @@ where
             Ok(info) => info,
             Err(err) => {
                 debug!("SptkValidator: No Specific query found ({})", err);
-                let response = HttpResponse::BadRequest().finish().map_into_right_body();
-                return Box::pin(async { Ok(ServiceResponse::new(request, response)) });
+                return Box::pin(reject(
+                    request,
+                    pl,
+                    HttpResponse::BadRequest().finish(),
+                    "no uuid in the query",
+                ));
             }
         };
 
@@ -82,8 +134,12 @@ where
                     "SptkValidator: Couldn't change the HeaderValue to str ({})",
                     err
                 );
-                let response = HttpResponse::BadRequest().finish().map_into_right_body();
-                return Box::pin(async { Ok(ServiceResponse::new(request, response)) });
+                return Box::pin(reject(
+                    request,
+                    pl,
+                    HttpResponse::BadRequest().finish(),
+                    "invalid SPTK header",
+                ));
             }
         };
 
@@ -101,20 +157,30 @@ where
             Ok(conn) => conn,
             Err(err) => {
                 error!("middleware: cannot get a auth_db connection: {}", err);
-                let response = HttpResponse::InternalServerError()
-                    .finish()
-                    .map_into_right_body();
-                return Box::pin(async { Ok(ServiceResponse::new(request, response)) });
+                return Box::pin(reject(
+                    request,
+                    pl,
+                    HttpResponse::InternalServerError().finish(),
+                    "auth database unavailable",
+                ));
             }
         };
 
         Box::pin(async move {
             let host_uuid = info.uuid.to_owned();
             // Get the APIKEY entry corresponding to the SPTK (token)
-            let api_key = actix_web::web::block(move || {
+            let api_key = match actix_web::web::block(move || {
                 ApiKey::get_by_key_berta(&mut conn, sptk.to_str().unwrap(), &CONFIG.berta_name)
             })
-            .await??;
+            .await?
+            {
+                Ok(api_key) => api_key,
+                Err(err) => {
+                    let reason = format!("cannot get the api key: {}", err);
+                    capture_unverified(&request, pl, reason).await;
+                    return Err(err.into());
+                }
+            };
 
             // If APIKEY.host_uuid is not None, we check that it's equals to
             // info.uuid (the ?uuid=XYZ) of the request. If it's the equals
@@ -127,17 +193,172 @@ where
                     res.await.map(ServiceResponse::map_into_left_body)
                 } else {
                     // Wrong pair of SPTK and HOST_UUID, return not authorized
-                    let response = HttpResponse::Unauthorized().finish().map_into_right_body();
-                    Ok(ServiceResponse::new(request, response))
+                    let response = HttpResponse::Unauthorized().finish();
+                    reject(request, pl, response, "SPTK bound to another host").await
+                }
+            } else if CONFIG.auto_enroll {
+                // Bind the APIKEY to the host ourselves (see auth::enroll)
+                let (key, uuid) = (sptk_owned.to_owned(), host_uuid.to_owned());
+                let remote_addr = request.peer_addr().map(|addr| addr.ip().to_string());
+                let enrolled =
+                    actix_web::web::block(move || enroll(&key, &uuid, remote_addr)).await??;
+
+                if enrolled {
+                    CHECKSPTK_CACHE.insert(host_uuid, sptk_owned);
+                    let res = svc.call(ServiceRequest::from_parts(request, pl));
+                    res.await.map(ServiceResponse::map_into_left_body)
+                } else {
+                    let response = HttpResponse::PreconditionFailed().finish();
+                    reject(request, pl, response, "api key could not be enrolled").await
                 }
             } else {
                 // Return 412 to signal the Client to update the field host_uuid
                 // on the APIKEY (using a call to the AUTH-SSOT server).
-                let response = HttpResponse::PreconditionFailed()
-                    .finish()
-                    .map_into_right_body();
-                Ok(ServiceResponse::new(request, response))
+                let response = HttpResponse::PreconditionFailed().finish();
+                reject(request, pl, response, "api key not bound to a host").await
             }
         })
     }
 }
+
+/// Check the signature of a signed request, returning the body (read to
+/// be verified) or the rejection (response, reason and body to dead letter).
+async fn check_signed(
+    request: &HttpRequest,
+    mut pl: dev::Payload,
+) -> Result<web::Bytes, (HttpResponse, String, dev::Payload)> {
+    let header = |name: &str| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|val| val.to_str().ok())
+            .unwrap_or_default()
+    };
+
+    let signed = match Signed::parse(
+        header("SP-TIMESTAMP"),
+        header("SP-NONCE"),
+        header("SP-SIGNATURE"),
+    ) {
+        Some(signed) => signed,
+        None => {
+            debug!("SptkValidator: invalid signature headers");
+            let reason = String::from("invalid signature headers");
+            return Err((HttpResponse::BadRequest().finish(), reason, pl));
+        }
+    };
+
+    let info = match web::Query::<Specific>::from_query(request.query_string()) {
+        Ok(info) => info.into_inner(),
+        Err(err) => {
+            debug!("SptkValidator: No Specific query found ({})", err);
+            let reason = String::from("no uuid in the query");
+            return Err((HttpResponse::BadRequest().finish(), reason, pl));
+        }
+    };
+
+    if !signed.is_fresh() {
+        debug!("SptkValidator: stale signed request for {}", info.uuid);
+        let reason = String::from("stale SP-TIMESTAMP");
+        return Err((HttpResponse::Unauthorized().finish(), reason, pl));
+    }
+
+    // The WebSocket upgrade (GET) has no body, reading it would consume the stream
+    let mut body = web::BytesMut::new();
+    if request.method() != Method::GET {
+        while let Some(chunk) = pl.next().await {
+            // Only part of the body was read, don't keep it
+            let Ok(chunk) = chunk else {
+                let reason = String::from("body unreadable (not kept)");
+                return Err((
+                    HttpResponse::BadRequest().finish(),
+                    reason,
+                    dev::Payload::None,
+                ));
+            };
+            if body.len() + chunk.len() > MAX_SIGNED_PAYLOAD_SIZE {
+                let reason = String::from("body over the size limit (not kept)");
+                return Err((
+                    HttpResponse::PayloadTooLarge().finish(),
+                    reason,
+                    dev::Payload::None,
+                ));
+            }
+            body.extend_from_slice(&chunk);
+        }
+    }
+    let body = body.freeze();
+    // The body was consumed, hand it back along the rejection
+    let reject = |response: HttpResponse, reason: &str| {
+        let pl = super::bytes_to_payload(body.clone());
+        (response, reason.to_owned(), pl)
+    };
+
+    let key = match CHECKSPTK_CACHE.get(&info.uuid) {
+        Some(key) => key,
+        None => {
+            let uuid = info.uuid.to_owned();
+            let key = web::block(move || match AUTHPOOL.get() {
+                Ok(mut conn) => fetch_host_key(&mut conn, &uuid),
+                Err(err) => Err(err.into()),
+            })
+            .await
+            .map_err(|_| {
+                let response = HttpResponse::InternalServerError().finish();
+                reject(response, "cannot fetch the api key")
+            })?;
+
+            match key {
+                Ok(Some(key)) => {
+                    CHECKSPTK_CACHE.insert(info.uuid.to_owned(), key.to_owned());
+                    key
+                }
+                Ok(None) => {
+                    debug!("SptkValidator: no api key bound to {}", info.uuid);
+                    let response = HttpResponse::Unauthorized().finish();
+                    return Err(reject(response, "no api key bound to the host"));
+                }
+                Err(err) => {
+                    error!("middleware: cannot fetch the api key: {}", err);
+                    let response = HttpResponse::InternalServerError().finish();
+                    return Err(reject(response, "cannot fetch the api key"));
+                }
+            }
+        }
+    };
+
+    let path = request
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or_default();
+    if !signed.verify(&key, request.method().as_str(), path, &body) {
+        debug!("SptkValidator: invalid signature for {}", info.uuid);
+        let response = HttpResponse::Unauthorized().finish();
+        return Err(reject(response, "invalid SP-SIGNATURE"));
+    }
+
+    // Only claimed once verified, so forged requests can't burn nonces
+    if !signed.claim_nonce(&info.uuid) {
+        debug!("SptkValidator: replayed nonce for {}", info.uuid);
+        let response = HttpResponse::Unauthorized().finish();
+        return Err(reject(response, "replayed SP-NONCE"));
+    }
+
+    Ok(body)
+}
+
+/// Dead letter (as unverified) the payload of a rejected request before
+/// answering it.
+async fn reject<B>(
+    request: HttpRequest,
+    pl: dev::Payload,
+    response: HttpResponse,
+    reason: impl Into<String>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    capture_unverified(&request, pl, reason.into()).await;
+    Ok(ServiceResponse::new(
+        request,
+        response.map_into_right_body(),
+    ))
+}
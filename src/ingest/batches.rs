@@ -0,0 +1,89 @@
+use moka::sync::Cache;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sproot::models::HttpHost;
+use std::time::Duration;
+
+use super::{IngestHost, RejectedSample};
+
+/// Longest batch id we accept, protect the cache against huge keys
+pub const MAX_BATCH_ID_LEN: usize = 128;
+
+/// Batch ids already applied, per host uuid. Agents retry within a few
+/// minutes so an hour is plenty to catch the duplicates.
+static SEEN_BATCHES: Lazy<Cache<(String, String), ()>> = Lazy::new(|| {
+    Cache::builder()
+        .max_capacity(1_000_000)
+        .time_to_live(Duration::from_secs(60 * 60))
+        .build()
+});
+
+/// Id of a batch, either a free form string or a sequence number
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum BatchId {
+    Seq(u64),
+    Name(String),
+}
+
+impl std::fmt::Display for BatchId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BatchId::Seq(seq) => write!(f, "{}", seq),
+            BatchId::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IngestBatch {
+    pub id: BatchId,
+    pub samples: Vec<IngestHost>,
+}
+
+/// Body of POST /api/hosts, either a list of batches carrying an id
+/// or the plain list of HttpHost sent by the older agents.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum IngestBody {
+    Batches { batches: Vec<IngestBatch> },
+    Legacy(Vec<IngestHost>),
+}
+
+impl IngestBody {
+    /// All the samples of the body, whatever the batch they belong to
+    pub fn samples_mut(&mut self) -> Vec<&mut HttpHost> {
+        match self {
+            IngestBody::Batches { batches } => batches
+                .iter_mut()
+                .flat_map(|batch| batch.samples.iter_mut())
+                .map(|sample| &mut sample.host)
+                .collect(),
+            IngestBody::Legacy(samples) => {
+                samples.iter_mut().map(|sample| &mut sample.host).collect()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct IngestReport {
+    pub applied: Vec<String>,
+    pub deduplicated: Vec<String>,
+    pub rejected: Vec<RejectedSample>,
+}
+
+/// Mark the batch `id` of the host `uuid` as seen.
+/// Return false if it was already seen (the batch is a duplicate).
+pub fn claim_batch(uuid: &str, id: &str) -> bool {
+    SEEN_BATCHES
+        .entry((uuid.to_owned(), id.to_owned()))
+        .or_insert(())
+        .is_fresh()
+}
+
+/// Forget about a batch which could not be applied, so that the
+/// agent's retry is not considered a duplicate.
+pub fn release_batch(uuid: &str, id: &str) {
+    SEEN_BATCHES.invalidate(&(uuid.to_owned(), id.to_owned()));
+}
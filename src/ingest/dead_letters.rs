@@ -0,0 +1,180 @@
+//! Dead letters: the payloads of authenticated hosts rejected by the ingest
+//! routes, and the rows the flusher couldn't write, are kept (bounded to
+//! dead_letters_max entries) so they can be inspected and replayed.
+//! Requests rejected by SptkValidator are kept as unverified: their uuid is
+//! only what the caller claims, so they are rate limited, bounded apart
+//! (flooding them can't evict the others) and never replayed.
+//! Bodies are stored decompressed and the secrets headers are dropped.
+use std::sync::Mutex;
+
+use actix_http::encoding::Decoder;
+use actix_web::http::header::HeaderMap;
+use actix_web::{dev, web, HttpRequest};
+use futures_util::StreamExt;
+use once_cell::sync::Lazy;
+use sproot::apierrors::ApiError;
+use sproot::models::Specific;
+
+use crate::auth::ingest_limiter::Bucket;
+use crate::models::{NewDeadLetter, Rows};
+use crate::utils::payload::MAX_PAYLOAD_SIZE;
+use crate::{CONFIG, METRICSPOOL};
+
+/// Route of the dead letters holding rows the flusher couldn't write
+pub const FLUSH_ROUTE: &str = "flush";
+
+/// Headers never stored
+const SECRET_HEADERS: [&str; 6] = [
+    "sptk",
+    "sp-signature",
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+];
+/// Headers describing the body as received, wrong once it's decompressed
+const ENCODING_HEADERS: [&str; 3] = ["content-encoding", "content-length", "transfer-encoding"];
+
+/// Seconds between two unverified dead letters (once the burst is used)
+const UNVERIFIED_INTERVAL: f64 = 2.0;
+/// Number of unverified dead letters which can be stored at once
+const UNVERIFIED_BURST: f64 = 10.0;
+
+/// Shared by every caller, as the uuid of the unverified ones can't be trusted
+static UNVERIFIED_BUCKET: Lazy<Mutex<Bucket>> =
+    Lazy::new(|| Mutex::new(Bucket::new(UNVERIFIED_BURST)));
+
+fn enabled() -> bool {
+    CONFIG.dead_letters_max > 0
+}
+
+fn query_uuid(req: &HttpRequest) -> Option<String> {
+    web::Query::<Specific>::from_query(req.query_string())
+        .ok()
+        .map(|info| info.into_inner().uuid)
+}
+
+fn headers_json(headers: &HeaderMap) -> serde_json::Value {
+    let mut map = serde_json::Map::new();
+    for (name, value) in headers {
+        let name = name.as_str();
+        if SECRET_HEADERS.contains(&name) || ENCODING_HEADERS.contains(&name) {
+            continue;
+        }
+        if let Ok(value) = value.to_str() {
+            map.insert(name.to_owned(), serde_json::Value::from(value));
+        }
+    }
+
+    serde_json::Value::Object(map)
+}
+
+/// Store the dead letter (blocking), errors are only logged
+fn store(letter: NewDeadLetter) {
+    let res = METRICSPOOL
+        .get()
+        .map_err(ApiError::from)
+        .and_then(|mut conn| letter.insert(&mut conn, CONFIG.dead_letters_max));
+    if let Err(err) = res {
+        error!(
+            "DeadLetters: cannot store the payload of {:?}: {}",
+            letter.host_uuid.as_ref().or(letter.claimed_uuid.as_ref()),
+            err
+        );
+    }
+}
+
+/// Store a rejected request whose (decompressed) body was already read,
+/// only to be called once SptkValidator authenticated the host
+pub async fn capture(req: &HttpRequest, body: &[u8], reason: String) {
+    if !enabled() {
+        return;
+    }
+
+    let letter = NewDeadLetter {
+        host_uuid: query_uuid(req),
+        claimed_uuid: None,
+        route: req.path().to_owned(),
+        reason,
+        headers: headers_json(req.headers()),
+        body: body.to_vec(),
+    };
+
+    let _ = web::block(move || store(letter)).await;
+}
+
+/// Read (and decompress) the body of a request rejected by SptkValidator
+/// then store it as unverified, unless too many were stored lately
+pub async fn capture_unverified(req: &HttpRequest, pl: dev::Payload, mut reason: String) {
+    if !enabled() {
+        return;
+    }
+    if let Err(wait) = UNVERIFIED_BUCKET
+        .lock()
+        .unwrap()
+        .take(UNVERIFIED_INTERVAL, UNVERIFIED_BURST)
+    {
+        debug!(
+            "DeadLetters: unverified payload not kept (limited for {}s)",
+            wait
+        );
+        return;
+    }
+
+    let mut body = web::BytesMut::new();
+    // The WebSocket upgrade (GET) has no body
+    if req.method() != actix_web::http::Method::GET {
+        let mut stream = Decoder::from_headers(pl, req.headers());
+        while let Some(chunk) = stream.next().await {
+            let Ok(chunk) = chunk else {
+                reason.push_str(" (body unreadable, not kept)");
+                body.clear();
+                break;
+            };
+            if body.len() + chunk.len() > MAX_PAYLOAD_SIZE {
+                reason.push_str(" (body over the size limit, not kept)");
+                body.clear();
+                break;
+            }
+            body.extend_from_slice(&chunk);
+        }
+    }
+
+    let letter = NewDeadLetter {
+        host_uuid: None,
+        claimed_uuid: query_uuid(req),
+        route: req.path().to_owned(),
+        reason,
+        headers: headers_json(req.headers()),
+        body: body.to_vec(),
+    };
+
+    let _ = web::block(move || store(letter)).await;
+}
+
+/// Store the rows of a host the flusher couldn't write (blocking)
+pub fn capture_rows(uuid: &str, rows: &Rows, reason: String) {
+    if !enabled() {
+        return;
+    }
+
+    let body = match serde_json::to_vec(rows) {
+        Ok(body) => body,
+        Err(err) => {
+            error!(
+                "DeadLetters: cannot serialize the rows of {}: {}",
+                uuid, err
+            );
+            return;
+        }
+    };
+
+    store(NewDeadLetter {
+        host_uuid: Some(uuid.to_owned()),
+        claimed_uuid: None,
+        route: FLUSH_ROUTE.to_owned(),
+        reason,
+        headers: serde_json::json!({ "content-type": "application/json" }),
+        body,
+    });
+}
@@ -0,0 +1,442 @@
+//! Receivers such as Prometheus' remote_write don't send a whole
+//! HttpHost at once: the series of a single scrape can be split over
+//! multiple requests. Samples are thus assembled into a Snapshot (one
+//! per host and timestamp) which is staged for a few seconds before
+//! being turned into rows and handed to the write-behind pipeline.
+use chrono::NaiveDateTime;
+use once_cell::sync::Lazy;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::models::{
+    HostUpsert, NewCpuStats, NewCpuTimes, NewCustomMetric, NewDisk, NewIoBlock, NewIoNet,
+    NewLoadAvg, NewMemory, NewSwap, Rows,
+};
+
+mod batches;
+mod dead_letters;
+mod pipeline;
+mod process;
+mod sample;
+mod skew;
+mod validation;
+
+pub use batches::*;
+pub use dead_letters::*;
+pub use pipeline::*;
+pub use process::*;
+pub use sample::*;
+pub use skew::*;
+pub use validation::*;
+
+/// How long a snapshot stays in the staging area waiting for its other parts
+const SETTLE_DELAY: Duration = Duration::from_secs(15);
+/// How often the sweeper checks the staging area for settled snapshots
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5);
+/// Upper bound of snapshots kept in memory, protect against unbounded growth
+const MAX_STAGED: usize = 50_000;
+/// Upper bound of snapshots of a single host, so that one host sending many
+/// timestamps doesn't fill the staging area for all the others
+const MAX_STAGED_PER_HOST: usize = 2_000;
+/// Size of the custom_metrics.name column
+const MAX_NAME_LEN: usize = 128;
+
+pub static STAGING: Lazy<Staging> = Lazy::new(Staging::default);
+
+/// Check that `name` fits in the custom_metrics.name column
+pub fn valid_metric_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= MAX_NAME_LEN
+}
+
+/// Everything known about a host at a given point in time
+#[derive(Debug)]
+pub struct Snapshot {
+    pub host: HostUpsert,
+    cpu_times: HashMap<(String, String), i64>,
+    soft_interrupts: HashMap<String, i64>,
+    memory_usage: HashMap<String, i64>,
+    swap_usage: HashMap<(String, String), i64>,
+    disk_usage: HashMap<(String, String), i64>,
+    pub cpustats: Option<NewCpuStats>,
+    pub loadavg: Option<NewLoadAvg>,
+    pub memory: Option<NewMemory>,
+    pub swap: Option<NewSwap>,
+    pub disks: BTreeMap<String, NewDisk>,
+    pub ioblocks: BTreeMap<String, NewIoBlock>,
+    pub ionets: BTreeMap<String, NewIoNet>,
+    pub customs: Vec<NewCustomMetric>,
+}
+
+impl Snapshot {
+    pub fn new(uuid: &str, created_at: NaiveDateTime) -> Self {
+        Self {
+            host: HostUpsert {
+                uuid: uuid.to_owned(),
+                created_at,
+                ..Default::default()
+            },
+            cpu_times: HashMap::new(),
+            soft_interrupts: HashMap::new(),
+            memory_usage: HashMap::new(),
+            swap_usage: HashMap::new(),
+            disk_usage: HashMap::new(),
+            cpustats: None,
+            loadavg: None,
+            memory: None,
+            swap: None,
+            disks: BTreeMap::new(),
+            ioblocks: BTreeMap::new(),
+            ionets: BTreeMap::new(),
+            customs: Vec::new(),
+        }
+    }
+
+    fn uuid(&self) -> String {
+        self.host.uuid.to_owned()
+    }
+
+    /// Set the time spent by `cpu` in `column` (a cputimes column), in ticks.
+    /// Values of each cpu are summed when the snapshot is turned into rows.
+    pub fn cpu_time(&mut self, cpu: &str, column: &str, ticks: i64) {
+        self.cpu_times
+            .insert((cpu.to_owned(), column.to_owned()), ticks);
+    }
+
+    /// Set the number of soft interrupts of a given kind (summed later)
+    pub fn soft_interrupts(&mut self, kind: &str, count: i64) {
+        self.soft_interrupts.insert(kind.to_owned(), count);
+    }
+
+    /// Set the memory in a given state (used, free...), the total of the
+    /// memory is the sum of the states.
+    pub fn memory_usage(&mut self, state: &str, bytes: i64) {
+        self.memory();
+        self.memory_usage.insert(state.to_owned(), bytes);
+    }
+
+    /// Set the swap of `device` in a given state (used or free), the
+    /// totals are summed over the devices.
+    pub fn swap_usage(&mut self, device: &str, state: &str, bytes: i64) {
+        self.swap();
+        self.swap_usage
+            .insert((device.to_owned(), state.to_owned()), bytes);
+    }
+
+    /// Set the space of the disk mounted on `mount_point` in a given state,
+    /// its total_space is the sum of the states.
+    pub fn disk_usage(&mut self, disk_name: &str, mount_point: &str, state: &str, bytes: i64) {
+        self.disk(disk_name, mount_point);
+        self.disk_usage
+            .insert((mount_point.to_owned(), state.to_owned()), bytes);
+    }
+
+    pub fn cpustats(&mut self) -> &mut NewCpuStats {
+        let (host_uuid, created_at) = (self.uuid(), self.host.created_at);
+        self.cpustats.get_or_insert_with(|| NewCpuStats {
+            host_uuid,
+            created_at,
+            ..Default::default()
+        })
+    }
+
+    pub fn loadavg(&mut self) -> &mut NewLoadAvg {
+        let (host_uuid, created_at) = (self.uuid(), self.host.created_at);
+        self.loadavg.get_or_insert_with(|| NewLoadAvg {
+            host_uuid,
+            created_at,
+            ..Default::default()
+        })
+    }
+
+    pub fn memory(&mut self) -> &mut NewMemory {
+        let (host_uuid, created_at) = (self.uuid(), self.host.created_at);
+        self.memory.get_or_insert_with(|| NewMemory {
+            host_uuid,
+            created_at,
+            ..Default::default()
+        })
+    }
+
+    pub fn swap(&mut self) -> &mut NewSwap {
+        let (host_uuid, created_at) = (self.uuid(), self.host.created_at);
+        self.swap.get_or_insert_with(|| NewSwap {
+            host_uuid,
+            created_at,
+            ..Default::default()
+        })
+    }
+
+    pub fn disk(&mut self, disk_name: &str, mount_point: &str) -> &mut NewDisk {
+        let (host_uuid, created_at) = (self.uuid(), self.host.created_at);
+        self.disks
+            .entry(mount_point.to_owned())
+            .or_insert_with(|| NewDisk {
+                disk_name: disk_name.to_owned(),
+                mount_point: mount_point.to_owned(),
+                host_uuid,
+                created_at,
+                ..Default::default()
+            })
+    }
+
+    pub fn ioblock(&mut self, device_name: &str) -> &mut NewIoBlock {
+        let (host_uuid, created_at) = (self.uuid(), self.host.created_at);
+        self.ioblocks
+            .entry(device_name.to_owned())
+            .or_insert_with(|| NewIoBlock {
+                device_name: device_name.to_owned(),
+                host_uuid,
+                created_at,
+                ..Default::default()
+            })
+    }
+
+    pub fn ionet(&mut self, interface: &str) -> &mut NewIoNet {
+        let (host_uuid, created_at) = (self.uuid(), self.host.created_at);
+        self.ionets
+            .entry(interface.to_owned())
+            .or_insert_with(|| NewIoNet {
+                interface: interface.to_owned(),
+                host_uuid,
+                created_at,
+                ..Default::default()
+            })
+    }
+
+    pub fn custom(&mut self, name: &str, labels: serde_json::Value, value: f64) {
+        if !valid_metric_name(name) {
+            debug!(
+                "Snapshot: ignoring custom metric with invalid name '{}'",
+                name
+            );
+            return;
+        }
+
+        self.customs.push(NewCustomMetric {
+            name: name.to_owned(),
+            labels,
+            value,
+            host_uuid: self.uuid(),
+            created_at: self.host.created_at,
+        });
+    }
+
+    /// Move the content of the snapshot into the rows to be inserted
+    pub fn into_rows(mut self, rows: &mut Rows) {
+        if !self.cpu_times.is_empty() {
+            let mut cputimes = NewCpuTimes {
+                host_uuid: self.uuid(),
+                created_at: self.host.created_at,
+                ..Default::default()
+            };
+            for ((_, column), ticks) in self.cpu_times.drain() {
+                match column.as_str() {
+                    "user" => cputimes.cuser += ticks,
+                    "nice" => cputimes.nice += ticks,
+                    "system" => cputimes.system += ticks,
+                    "idle" => cputimes.idle += ticks,
+                    "iowait" => cputimes.iowait += ticks,
+                    "irq" => cputimes.irq += ticks,
+                    "softirq" => cputimes.softirq += ticks,
+                    "steal" => cputimes.steal += ticks,
+                    "guest" => cputimes.guest += ticks,
+                    "guest_nice" => cputimes.guest_nice += ticks,
+                    _ => {}
+                }
+            }
+            rows.cputimes.push(cputimes);
+        }
+
+        if !self.soft_interrupts.is_empty() {
+            let total = self.soft_interrupts.values().sum();
+            self.cpustats().soft_interrupts = total;
+        }
+
+        if !self.memory_usage.is_empty() {
+            let total = self.memory_usage.values().sum();
+            self.memory().total = total;
+        }
+        if !self.swap_usage.is_empty() {
+            let state = |name: &str| {
+                self.swap_usage
+                    .iter()
+                    .filter(|((_, state), _)| state == name)
+                    .map(|(_, bytes)| bytes)
+                    .sum::<i64>()
+            };
+            let (used, free) = (state("used"), state("free"));
+            let total = self.swap_usage.values().sum();
+            let swap = self.swap();
+            (swap.total, swap.used, swap.free) = (total, used, free);
+        }
+        for ((mount_point, _), bytes) in self.disk_usage.drain() {
+            if let Some(disk) = self.disks.get_mut(&mount_point) {
+                disk.total_space += bytes;
+            }
+        }
+
+        // Sources usually don't report the used memory directly
+        if let Some(mut memory) = self.memory {
+            if memory.used == 0 {
+                memory.used = (memory.total - memory.free - memory.buffers - memory.cached).max(0);
+            }
+            rows.memory.push(memory);
+        }
+        if let Some(mut swap) = self.swap {
+            if swap.used == 0 {
+                swap.used = (swap.total - swap.free).max(0);
+            }
+            rows.swap.push(swap);
+        }
+
+        rows.hosts.push(self.host);
+        rows.cpustats.extend(self.cpustats);
+        rows.loadavg.extend(self.loadavg);
+        rows.disks.extend(self.disks.into_values());
+        rows.ioblocks.extend(self.ioblocks.into_values());
+        rows.ionets.extend(self.ionets.into_values());
+        rows.customs.append(&mut self.customs);
+    }
+}
+
+struct Staged {
+    since: Instant,
+    snapshot: Snapshot,
+}
+
+#[derive(Default)]
+struct Entries {
+    snapshots: HashMap<(String, NaiveDateTime), Staged>,
+    /// Number of snapshots staged for each host
+    per_host: HashMap<String, usize>,
+}
+
+#[derive(Default)]
+pub struct Staging {
+    entries: Mutex<Entries>,
+}
+
+/// Access to the staged snapshots of a single host
+pub struct Stage<'a> {
+    uuid: &'a str,
+    entries: &'a mut Entries,
+}
+
+impl Stage<'_> {
+    /// Get the snapshot of the host at `created_at`, creating it if needed
+    pub fn at(&mut self, created_at: NaiveDateTime) -> &mut Snapshot {
+        let Entries {
+            snapshots,
+            per_host,
+        } = &mut *self.entries;
+
+        &mut snapshots
+            .entry((self.uuid.to_owned(), created_at))
+            .or_insert_with(|| {
+                *per_host.entry(self.uuid.to_owned()).or_default() += 1;
+                Staged {
+                    since: Instant::now(),
+                    snapshot: Snapshot::new(self.uuid, created_at),
+                }
+            })
+            .snapshot
+    }
+}
+
+impl Staging {
+    /// Run `f` with exclusive access to the snapshots of the host `uuid`.
+    /// Return false if the staging area, or the share of the host, is full.
+    pub fn with<F: FnOnce(&mut Stage)>(&self, uuid: &str, f: F) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.snapshots.len() >= MAX_STAGED {
+            return false;
+        }
+        if entries.per_host.get(uuid).copied().unwrap_or_default() >= MAX_STAGED_PER_HOST {
+            debug!("Staging: too many snapshots staged for {}", uuid);
+            return false;
+        }
+
+        f(&mut Stage {
+            uuid,
+            entries: &mut entries,
+        });
+        true
+    }
+
+    /// Take out the snapshots which have been staged for long enough
+    pub fn drain_settled(&self) -> Rows {
+        let mut rows = Rows::default();
+        let mut entries = self.entries.lock().unwrap();
+
+        let settled: Vec<_> = entries
+            .snapshots
+            .iter()
+            .filter(|(_, staged)| staged.since.elapsed() >= SETTLE_DELAY)
+            .map(|(key, _)| key.to_owned())
+            .collect();
+        for key in settled {
+            if let Some(staged) = entries.snapshots.remove(&key) {
+                staged.snapshot.into_rows(&mut rows);
+            }
+            if let Some(count) = entries.per_host.get_mut(&key.0) {
+                *count -= 1;
+                if *count == 0 {
+                    entries.per_host.remove(&key.0);
+                }
+            }
+        }
+
+        rows
+    }
+}
+
+/// Start the thread moving the settled snapshots to the pipeline
+pub fn spawn_sweeper() {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(SWEEP_INTERVAL);
+
+        // Keep the snapshots staged while the pipeline is full, the
+        // receivers will get a 503 once the staging area is full too.
+        if PIPELINE.is_full() {
+            continue;
+        }
+
+        let rows = STAGING.drain_settled();
+        if !rows.is_empty() {
+            PIPELINE.push(rows);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(ts: i64) -> NaiveDateTime {
+        chrono::DateTime::from_timestamp(ts, 0).unwrap().naive_utc()
+    }
+
+    #[test]
+    fn staging_caps_each_host() {
+        let staging = Staging::default();
+
+        assert!(staging.with("noisy", |stage| {
+            for ts in 0..MAX_STAGED_PER_HOST as i64 {
+                stage.at(date(ts));
+            }
+        }));
+        // Only the host at its cap is refused
+        assert!(!staging.with("noisy", |stage| {
+            stage.at(date(0));
+        }));
+        assert!(staging.with("other", |stage| {
+            stage.at(date(0));
+        }));
+
+        let entries = staging.entries.lock().unwrap();
+        assert_eq!(entries.snapshots.len(), MAX_STAGED_PER_HOST + 1);
+        assert_eq!(entries.per_host["noisy"], MAX_STAGED_PER_HOST);
+        assert_eq!(entries.per_host["other"], 1);
+    }
+}
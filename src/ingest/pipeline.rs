@@ -0,0 +1,202 @@
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::HttpResponse;
+use diesel::{sql_query, RunQueryDsl};
+use once_cell::sync::Lazy;
+use sproot::Pool;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::models::Rows;
+use crate::CONFIG;
+
+use super::{capture_rows, release_batch};
+
+pub static PIPELINE: Lazy<Pipeline> = Lazy::new(Pipeline::default);
+
+/// Longest wait between two attempts to write while the database is unreachable
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Rows waiting to be written by the flusher. Requests only enqueue
+/// their rows and return, the flusher merges everything that has been
+/// queued and write it in bulk (one multi-row INSERT per table).
+#[derive(Default)]
+pub struct Pipeline {
+    queue: Mutex<Rows>,
+    wakeup: Condvar,
+}
+
+impl Pipeline {
+    /// Add the rows to the queue. Return false if the queue is full.
+    pub fn enqueue(&self, rows: Rows) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() >= CONFIG.ingest_queue_capacity {
+            return false;
+        }
+
+        self.push_locked(&mut queue, rows);
+        true
+    }
+
+    /// Add the rows to the queue even if it is full. Only meant for
+    /// producers which are already bounded (such as the staging area).
+    pub fn push(&self, rows: Rows) {
+        let mut queue = self.queue.lock().unwrap();
+        self.push_locked(&mut queue, rows);
+    }
+
+    /// Number of rows waiting to be written
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.queue.lock().unwrap().len() >= CONFIG.ingest_queue_capacity
+    }
+
+    fn push_locked(&self, queue: &mut Rows, rows: Rows) {
+        queue.append(rows);
+        if queue.len() >= CONFIG.ingest_flush_rows {
+            self.wakeup.notify_one();
+        }
+    }
+
+    /// Wait until enough rows are queued or the flush interval is elapsed,
+    /// then take everything out of the queue.
+    fn wait_batch(&self) -> Rows {
+        let interval = Duration::from_secs(CONFIG.ingest_flush_interval);
+        let deadline = Instant::now() + interval;
+        let mut queue = self.queue.lock().unwrap();
+
+        while queue.len() < CONFIG.ingest_flush_rows {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            queue = self.wakeup.wait_timeout(queue, left).unwrap().0;
+        }
+
+        std::mem::take(&mut *queue)
+    }
+}
+
+/// Response sent when the queue is full, the agent is expected to
+/// retry after the next flush.
+pub fn busy() -> HttpResponse {
+    HttpResponse::ServiceUnavailable()
+        .insert_header((RETRY_AFTER, CONFIG.ingest_flush_interval.max(1).to_string()))
+        .body("too many samples are waiting to be written")
+}
+
+/// Start the thread writing the queued rows to the database.
+/// While the database can't be reached the rows are put back in the queue
+/// (which stays bounded by ingest_queue_capacity, the agents get 503 once
+/// it is full) and retried with an exponential backoff.
+pub fn spawn_flusher(pool: Pool) {
+    std::thread::spawn(move || {
+        let mut backoff = Duration::ZERO;
+        loop {
+            let rows = PIPELINE.wait_batch();
+            if rows.is_empty() {
+                continue;
+            }
+
+            trace!("Flusher: writing {} rows", rows.len());
+            match write(&pool, rows) {
+                None => backoff = Duration::ZERO,
+                Some(rows) => {
+                    backoff = next_backoff(backoff, CONFIG.ingest_flush_interval);
+                    warn!(
+                        "Flusher: database unreachable, retrying {} rows in {:?}",
+                        rows.len(),
+                        backoff
+                    );
+                    PIPELINE.push(rows);
+                    std::thread::sleep(backoff);
+                }
+            }
+        }
+    });
+}
+
+/// Double the backoff, from the flush interval up to MAX_RETRY_BACKOFF
+/// (the interval alone if it is longer than that)
+fn next_backoff(backoff: Duration, interval: u64) -> Duration {
+    (backoff * 2)
+        .min(MAX_RETRY_BACKOFF)
+        .max(Duration::from_secs(interval))
+}
+
+/// Write the rows, giving back those to retry if the database can't be reached
+fn write(pool: &Pool, rows: Rows) -> Option<Rows> {
+    let res = match pool.get() {
+        Ok(mut conn) => rows.insert(&mut conn),
+        Err(err) => Err(err.into()),
+    };
+    let Err(err) = res else {
+        return None;
+    };
+
+    error!("Flusher: cannot write {} rows: {}", rows.len(), err);
+    if !reachable(pool) {
+        return Some(rows);
+    }
+    write_per_host(pool, rows)
+}
+
+/// Write the rows of each host separately, so a single invalid row only
+/// costs the rows of its host, which are dead lettered (and their batches
+/// released). The rows are given back if the database becomes unreachable.
+fn write_per_host(pool: &Pool, rows: Rows) -> Option<Rows> {
+    let mut retry = Rows::default();
+    for (uuid, rows) in rows.split_by_host() {
+        let res = match pool.get() {
+            Ok(mut conn) => rows.insert(&mut conn),
+            Err(err) => Err(err.into()),
+        };
+        let Err(err) = res else {
+            continue;
+        };
+
+        if !reachable(pool) {
+            retry.append(rows);
+            continue;
+        }
+        capture_rows(&uuid, &rows, format!("cannot write the rows: {}", err));
+        for (uuid, id) in &rows.batches {
+            release_batch(uuid, id);
+        }
+    }
+
+    (!retry.is_empty()).then_some(retry)
+}
+
+/// Whether the database answers, to tell an outage from rows it refuses
+fn reachable(pool: &Pool) -> bool {
+    match pool.get() {
+        Ok(mut conn) => sql_query("SELECT 1").execute(&mut conn).is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_max() {
+        let mut backoff = Duration::ZERO;
+        let mut steps = Vec::new();
+        for _ in 0..7 {
+            backoff = next_backoff(backoff, 2);
+            steps.push(backoff.as_secs());
+        }
+        assert_eq!(steps, vec![2, 4, 8, 16, 32, 60, 60]);
+    }
+
+    #[test]
+    fn backoff_with_a_long_interval() {
+        let backoff = next_backoff(Duration::ZERO, 120);
+        assert_eq!(backoff, Duration::from_secs(120));
+        assert_eq!(next_backoff(backoff, 120), Duration::from_secs(120));
+    }
+}
@@ -0,0 +1,169 @@
+use sproot::apierrors::ApiError;
+
+use crate::models::{HostUpsert, Rows};
+
+use super::{
+    claim_batch, correct_skew, measure_skew, push_valid_samples, release_batch, IngestBody,
+    IngestHost, IngestReport, MAX_BATCH_ID_LEN, PIPELINE,
+};
+
+pub enum Outcome {
+    /// The valid samples have been queued, `accepted` is false if none was
+    Queued {
+        report: IngestReport,
+        accepted: bool,
+    },
+    /// The pipeline is full, nothing has been queued
+    Busy,
+}
+
+/// Handle the body sent by an agent: correct the clock skew, skip the
+/// batches already applied and the invalid samples, then queue the rest.
+/// `batch_id` is used for bodies which don't carry their own batch ids.
+pub fn ingest_body(
+    uuid: &str,
+    mut body: IngestBody,
+    batch_id: Option<&str>,
+) -> Result<Outcome, ApiError> {
+    // Measure the skew of the host's clock and correct the samples if needed
+    let now = chrono::Utc::now().naive_utc();
+    let mut samples = body.samples_mut();
+    let skew = measure_skew(&samples, now);
+    if let Some(skew) = skew {
+        if let Err(err) = correct_skew(&mut samples, skew, now) {
+            // Still record the skew so that it can be seen from GET /api/host
+            let rows = Rows {
+                hosts: vec![HostUpsert {
+                    uuid: uuid.to_owned(),
+                    clock_skew: Some(skew),
+                    created_at: now,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            };
+            if !PIPELINE.enqueue(rows) {
+                return Ok(Outcome::Busy);
+            }
+            return Err(ApiError::InvalidRequestError(Some(err)));
+        }
+    }
+
+    // Samples without a batch id (older agents) are never deduplicated
+    let batches: Vec<(Option<String>, Vec<IngestHost>)> = match body {
+        IngestBody::Batches { batches } => batches
+            .into_iter()
+            .map(|batch| (Some(batch.id.to_string()), batch.samples))
+            .collect(),
+        IngestBody::Legacy(samples) => vec![(batch_id.map(str::to_owned), samples)],
+    };
+
+    // Check all the ids before claiming any of them
+    if let Some(id) = batches
+        .iter()
+        .filter_map(|(id, _)| id.as_ref())
+        .find(|id| id.is_empty() || id.len() > MAX_BATCH_ID_LEN)
+    {
+        return Err(ApiError::InvalidRequestError(Some(format!(
+            "batch id '{}' must be between 1 and {} characters",
+            id, MAX_BATCH_ID_LEN
+        ))));
+    }
+
+    let mut report = IngestReport::default();
+    let mut rows = collect_batches(uuid, batches, &mut report);
+    for host in &mut rows.hosts {
+        host.clock_skew = skew;
+    }
+    rows.batches = report
+        .applied
+        .iter()
+        .map(|id| (uuid.to_owned(), id.to_owned()))
+        .collect();
+
+    let accepted = !rows.is_empty();
+    if accepted && !PIPELINE.enqueue(rows) {
+        for id in &report.applied {
+            release_batch(uuid, id);
+        }
+        return Ok(Outcome::Busy);
+    }
+
+    Ok(Outcome::Queued { report, accepted })
+}
+
+/// Claim the batches and collect the rows of their valid samples. The
+/// claim of a batch producing no rows (every sample rejected) is released
+/// so that a corrected retry (or replay) of it isn't deduplicated.
+fn collect_batches(
+    uuid: &str,
+    batches: Vec<(Option<String>, Vec<IngestHost>)>,
+    report: &mut IngestReport,
+) -> Rows {
+    let mut rows = Rows::default();
+    for (id, samples) in batches {
+        if let Some(id) = &id {
+            if !claim_batch(uuid, id) {
+                report.deduplicated.push(id.to_owned());
+                continue;
+            }
+        }
+        let mut batch = Rows::default();
+        push_valid_samples(
+            &mut batch,
+            &mut report.rejected,
+            uuid,
+            id.as_deref(),
+            &samples,
+        );
+        if batch.is_empty() {
+            if let Some(id) = &id {
+                release_batch(uuid, id);
+            }
+            continue;
+        }
+        rows.append(batch);
+        report.applied.extend(id);
+    }
+
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(uptime: i64) -> IngestHost {
+        serde_json::from_value(serde_json::json!({
+            "system": "Linux",
+            "os_version": "6.1",
+            "hostname": "host",
+            "uptime": uptime,
+            "created_at": chrono::Utc::now().naive_utc(),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn rejected_batches_are_released() {
+        let uuid = "process-test-host";
+        let batches = || {
+            vec![
+                (Some(String::from("valid")), vec![sample(10)]),
+                (Some(String::from("rejected")), vec![sample(-1)]),
+            ]
+        };
+
+        let mut report = IngestReport::default();
+        let rows = collect_batches(uuid, batches(), &mut report);
+        assert!(!rows.is_empty());
+        assert_eq!(report.applied, vec![String::from("valid")]);
+        assert_eq!(report.rejected.len(), 1);
+
+        // Only the rejected batch can be sent again
+        let mut report = IngestReport::default();
+        collect_batches(uuid, batches(), &mut report);
+        assert_eq!(report.deduplicated, vec![String::from("valid")]);
+        assert!(report.applied.is_empty());
+        assert_eq!(report.rejected.len(), 1);
+    }
+}
@@ -0,0 +1,227 @@
+use serde::Deserialize;
+use sproot::models::HttpHost;
+
+use crate::models::{
+    NewContainer, NewKernelStats, NewProcess, NewSensor, NewSmart, NewTcpStates, Rows, UnitUpsert,
+};
+
+/// A sample sent by an agent: the HttpHost known by sproot, along with
+/// the optional metrics only this server knows about.
+#[derive(Debug, Deserialize)]
+pub struct IngestHost {
+    #[serde(flatten)]
+    pub host: HttpHost,
+    pub processes: Option<Vec<ProcessSample>>,
+    pub containers: Option<Vec<ContainerSample>>,
+    pub sensors: Option<Vec<SensorSample>>,
+    pub smart: Option<Vec<SmartSample>>,
+    pub tcp_states: Option<TcpStatesSample>,
+    pub kernel_stats: Option<KernelStatsSample>,
+    pub units: Option<Vec<UnitSample>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProcessSample {
+    pub pid: i32,
+    pub name: String,
+    /// Hash of the command line, computed by the agent
+    pub cmdline_hash: String,
+    /// Cpu usage in percent (of a single core)
+    pub cpu: f64,
+    /// Resident memory in bytes
+    pub rss: i64,
+    pub read_bytes: i64,
+    pub write_bytes: i64,
+}
+
+/// Counters of a container (or any cgroup, such as a systemd service)
+#[derive(Debug, Deserialize)]
+pub struct ContainerSample {
+    /// Id of the container, or path of the cgroup
+    pub container_id: String,
+    pub name: String,
+    /// Cpu time consumed, in microseconds
+    pub cpu_usage: i64,
+    /// Memory used, in bytes
+    pub memory_usage: i64,
+    /// Memory limit, in bytes (0 if unlimited)
+    pub memory_limit: i64,
+    pub read_bytes: i64,
+    pub write_bytes: i64,
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+}
+
+/// Kinds of sensors we know about
+pub const SENSOR_KINDS: [&str; 2] = ["temperature", "fan"];
+
+#[derive(Debug, Deserialize)]
+pub struct SensorSample {
+    pub label: String,
+    /// One of SENSOR_KINDS
+    pub kind: String,
+    /// Degrees Celsius for temperatures, RPM for fans
+    pub value: f64,
+    /// Critical threshold reported by the hardware, if any
+    pub crit: Option<f64>,
+}
+
+/// SMART attributes of a disk
+#[derive(Debug, Deserialize)]
+pub struct SmartSample {
+    pub disk_name: String,
+    pub reallocated_sectors: i64,
+    pub pending_sectors: i64,
+    /// Percentage of the rated endurance used (SSD only)
+    pub wear_level: Option<i64>,
+    pub power_on_hours: i64,
+    /// Whether the overall health self-assessment failed
+    pub failing: bool,
+}
+
+/// Number of TCP sockets in each state
+#[derive(Debug, Deserialize)]
+pub struct TcpStatesSample {
+    pub established: i64,
+    pub syn_sent: i64,
+    pub syn_recv: i64,
+    pub fin_wait1: i64,
+    pub fin_wait2: i64,
+    pub time_wait: i64,
+    pub close: i64,
+    pub close_wait: i64,
+    pub last_ack: i64,
+    pub listen: i64,
+    pub closing: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KernelStatsSample {
+    /// Allocated file descriptors (system wide)
+    pub open_fds: i64,
+    /// Maximum number of file descriptors (fs.file-max)
+    pub max_fds: i64,
+    /// Entries in the conntrack table (0 if not loaded)
+    pub conntrack_count: i64,
+    pub conntrack_max: i64,
+    /// Available entropy, in bits
+    pub entropy: i64,
+}
+
+/// ActiveState values of a systemd unit
+pub const UNIT_STATES: [&str; 8] = [
+    "active",
+    "reloading",
+    "inactive",
+    "failed",
+    "activating",
+    "deactivating",
+    "maintenance",
+    "refreshing",
+];
+
+/// State of a systemd unit the agent has been configured to watch
+#[derive(Debug, Deserialize)]
+pub struct UnitSample {
+    pub unit: String,
+    /// One of UNIT_STATES
+    pub state: String,
+    /// Number of restarts (NRestarts) since the unit was loaded
+    pub restarts: i64,
+}
+
+impl IngestHost {
+    /// Add the rows carried by the sample
+    pub fn push_rows(&self, uuid: &str, rows: &mut Rows) {
+        rows.push_host(uuid, &self.host);
+
+        let created_at = self.host.created_at;
+        for value in self.processes.iter().flatten() {
+            rows.processes.push(NewProcess {
+                pid: value.pid,
+                name: value.name.to_owned(),
+                cmdline_hash: value.cmdline_hash.to_owned(),
+                cpu: value.cpu,
+                rss: value.rss,
+                read_bytes: value.read_bytes,
+                write_bytes: value.write_bytes,
+                host_uuid: uuid.to_owned(),
+                created_at,
+            });
+        }
+        for value in self.containers.iter().flatten() {
+            rows.containers.push(NewContainer {
+                container_id: value.container_id.to_owned(),
+                name: value.name.to_owned(),
+                cpu_usage: value.cpu_usage,
+                memory_usage: value.memory_usage,
+                memory_limit: value.memory_limit,
+                read_bytes: value.read_bytes,
+                write_bytes: value.write_bytes,
+                rx_bytes: value.rx_bytes,
+                tx_bytes: value.tx_bytes,
+                host_uuid: uuid.to_owned(),
+                created_at,
+            });
+        }
+        for value in self.sensors.iter().flatten() {
+            rows.sensors.push(NewSensor {
+                label: value.label.to_owned(),
+                kind: value.kind.to_owned(),
+                value: value.value,
+                crit: value.crit,
+                host_uuid: uuid.to_owned(),
+                created_at,
+            });
+        }
+        for value in self.smart.iter().flatten() {
+            rows.smart.push(NewSmart {
+                disk_name: value.disk_name.to_owned(),
+                reallocated_sectors: value.reallocated_sectors,
+                pending_sectors: value.pending_sectors,
+                wear_level: value.wear_level,
+                power_on_hours: value.power_on_hours,
+                failing: value.failing as i32,
+                host_uuid: uuid.to_owned(),
+                created_at,
+            });
+        }
+        if let Some(value) = &self.tcp_states {
+            rows.tcpstates.push(NewTcpStates {
+                established: value.established,
+                syn_sent: value.syn_sent,
+                syn_recv: value.syn_recv,
+                fin_wait1: value.fin_wait1,
+                fin_wait2: value.fin_wait2,
+                time_wait: value.time_wait,
+                close: value.close,
+                close_wait: value.close_wait,
+                last_ack: value.last_ack,
+                listen: value.listen,
+                closing: value.closing,
+                host_uuid: uuid.to_owned(),
+                created_at,
+            });
+        }
+        if let Some(value) = &self.kernel_stats {
+            rows.kernelstats.push(NewKernelStats {
+                open_fds: value.open_fds,
+                max_fds: value.max_fds,
+                conntrack_count: value.conntrack_count,
+                conntrack_max: value.conntrack_max,
+                entropy: value.entropy,
+                host_uuid: uuid.to_owned(),
+                created_at,
+            });
+        }
+        for value in self.units.iter().flatten() {
+            rows.units.push(UnitUpsert {
+                unit: value.unit.to_owned(),
+                state: value.state.to_owned(),
+                restarts: value.restarts,
+                host_uuid: uuid.to_owned(),
+                created_at,
+            });
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use chrono::{Duration, NaiveDateTime};
+use serde::Deserialize;
+use sproot::models::HttpHost;
+
+use crate::CONFIG;
+
+/// What to do with the samples of a host whose clock is off by more
+/// than CONFIG.clock_skew_tolerance.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SkewPolicy {
+    /// Save the samples as is, only record the skew
+    #[default]
+    Accept,
+    /// Refuse the whole request
+    Reject,
+    /// Bring the samples dated in the future back to the server time
+    Clamp,
+    /// Shift all the samples by the measured skew
+    Rebase,
+}
+
+/// Measure the skew of the host's clock (in ms, positive if the host is
+/// ahead) from the newest sample of the request, which the agent sends
+/// right after collecting it.
+pub fn measure_skew(samples: &[&mut HttpHost], now: NaiveDateTime) -> Option<i64> {
+    samples
+        .iter()
+        .map(|item| item.created_at)
+        .max()
+        .map(|newest| (newest - now).num_milliseconds())
+}
+
+/// Apply CONFIG.clock_skew_policy to the samples, return an error
+/// if the policy is to reject them.
+pub fn correct_skew(
+    samples: &mut [&mut HttpHost],
+    skew: i64,
+    now: NaiveDateTime,
+) -> Result<(), String> {
+    let tolerance = Duration::seconds(CONFIG.clock_skew_tolerance);
+    if skew.abs() <= tolerance.num_milliseconds() {
+        return Ok(());
+    }
+
+    match CONFIG.clock_skew_policy {
+        SkewPolicy::Accept => {}
+        SkewPolicy::Reject => {
+            return Err(format!(
+                "the clock of the host is off by {}ms (tolerance is {}s)",
+                skew, CONFIG.clock_skew_tolerance
+            ))
+        }
+        SkewPolicy::Clamp => {
+            for item in samples.iter_mut() {
+                if item.created_at > now + tolerance {
+                    item.created_at = now;
+                }
+            }
+        }
+        SkewPolicy::Rebase => {
+            let shift = Duration::milliseconds(skew);
+            for item in samples.iter_mut() {
+                item.created_at -= shift;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,259 @@
+use chrono::{Duration, NaiveDateTime};
+use serde::Serialize;
+use sproot::models::HttpHost;
+
+use crate::models::Rows;
+
+use super::{IngestHost, SENSOR_KINDS, UNIT_STATES};
+
+/// Samples dated further in the future than this are rejected
+const MAX_FUTURE_DRIFT: Duration = Duration::minutes(5);
+/// Size of the VARCHAR columns (hosts.system, disks.disk_name, ...)
+const MAX_VARCHAR_LEN: usize = 128;
+/// Size of the hosts.hostname column
+const MAX_HOSTNAME_LEN: usize = 64;
+/// Size of the processes.cmdline_hash column
+const MAX_HASH_LEN: usize = 64;
+
+#[derive(Debug, Serialize)]
+pub struct RejectedSample {
+    /// Id of the batch the sample belongs to, if any
+    pub batch: Option<String>,
+    /// Position of the sample in its batch (or in the body), starting at 0
+    pub index: usize,
+    pub created_at: NaiveDateTime,
+    pub reasons: Vec<String>,
+}
+
+macro_rules! check_counters {
+    ($reasons:expr, $prefix:expr, $value:expr, [$($field:ident),*]) => {
+        $(
+            if $value.$field < 0 {
+                $reasons.push(format!("{}.{} is negative", $prefix, stringify!($field)));
+            }
+        )*
+    };
+}
+
+fn check_len(reasons: &mut Vec<String>, name: &str, value: &str, max: usize) {
+    if value.len() > max {
+        reasons.push(format!("{} is longer than {} characters", name, max));
+    }
+}
+
+/// Check that the sample can be saved as is, return the reasons why
+/// it can't (empty if the sample is valid).
+pub fn check_sample(sample: &IngestHost, now: NaiveDateTime) -> Vec<String> {
+    let mut reasons = Vec::new();
+
+    check_host(&mut reasons, &sample.host, now);
+    for (idx, value) in sample.processes.iter().flatten().enumerate() {
+        let prefix = format!("processes[{}]", idx);
+        check_len(&mut reasons, &prefix, &value.name, MAX_VARCHAR_LEN);
+        check_len(&mut reasons, &prefix, &value.cmdline_hash, MAX_HASH_LEN);
+        check_counters!(reasons, prefix, value, [pid, rss, read_bytes, write_bytes]);
+        if !value.cpu.is_finite() || value.cpu < 0.0 {
+            reasons.push(format!("{}.cpu is not a positive number", prefix));
+        }
+    }
+    for (idx, value) in sample.containers.iter().flatten().enumerate() {
+        let prefix = format!("containers[{}]", idx);
+        check_len(&mut reasons, &prefix, &value.container_id, MAX_VARCHAR_LEN);
+        check_len(&mut reasons, &prefix, &value.name, MAX_VARCHAR_LEN);
+        check_counters!(
+            reasons,
+            prefix,
+            value,
+            [
+                cpu_usage,
+                memory_usage,
+                memory_limit,
+                read_bytes,
+                write_bytes,
+                rx_bytes,
+                tx_bytes
+            ]
+        );
+    }
+    for (idx, value) in sample.sensors.iter().flatten().enumerate() {
+        let prefix = format!("sensors[{}]", idx);
+        check_len(&mut reasons, &prefix, &value.label, MAX_VARCHAR_LEN);
+        if !SENSOR_KINDS.contains(&value.kind.as_str()) {
+            reasons.push(format!("{}.kind must be one of {:?}", prefix, SENSOR_KINDS));
+        }
+        if !value.value.is_finite() || !value.crit.unwrap_or_default().is_finite() {
+            reasons.push(format!("{} has a non finite value", prefix));
+        }
+    }
+    for (idx, value) in sample.smart.iter().flatten().enumerate() {
+        let prefix = format!("smart[{}]", idx);
+        check_len(&mut reasons, &prefix, &value.disk_name, MAX_VARCHAR_LEN);
+        check_counters!(
+            reasons,
+            prefix,
+            value,
+            [reallocated_sectors, pending_sectors, power_on_hours]
+        );
+        if value.wear_level.is_some_and(|wear| wear < 0) {
+            reasons.push(format!("{}.wear_level is negative", prefix));
+        }
+    }
+    if let Some(value) = &sample.tcp_states {
+        check_counters!(
+            reasons,
+            "tcp_states",
+            value,
+            [
+                established,
+                syn_sent,
+                syn_recv,
+                fin_wait1,
+                fin_wait2,
+                time_wait,
+                close,
+                close_wait,
+                last_ack,
+                listen,
+                closing
+            ]
+        );
+    }
+    if let Some(value) = &sample.kernel_stats {
+        check_counters!(
+            reasons,
+            "kernel_stats",
+            value,
+            [open_fds, max_fds, conntrack_count, conntrack_max, entropy]
+        );
+    }
+    for (idx, value) in sample.units.iter().flatten().enumerate() {
+        let prefix = format!("units[{}]", idx);
+        check_len(&mut reasons, &prefix, &value.unit, MAX_VARCHAR_LEN);
+        check_counters!(reasons, prefix, value, [restarts]);
+        if !UNIT_STATES.contains(&value.state.as_str()) {
+            reasons.push(format!("{}.state must be one of {:?}", prefix, UNIT_STATES));
+        }
+    }
+
+    reasons
+}
+
+/// Reject the samples dated further than MAX_FUTURE_DRIFT in the future
+pub fn check_future(reasons: &mut Vec<String>, created_at: NaiveDateTime, now: NaiveDateTime) {
+    if created_at > now + MAX_FUTURE_DRIFT {
+        reasons.push(format!("created_at {} is in the future", created_at));
+    }
+}
+
+fn check_host(reasons: &mut Vec<String>, item: &HttpHost, now: NaiveDateTime) {
+    check_future(reasons, item.created_at, now);
+    if item.uptime < 0 {
+        reasons.push(String::from("uptime is negative"));
+    }
+    check_len(reasons, "system", &item.system, MAX_VARCHAR_LEN);
+    check_len(reasons, "os_version", &item.os_version, MAX_VARCHAR_LEN);
+    check_len(reasons, "hostname", &item.hostname, MAX_HOSTNAME_LEN);
+
+    if let Some(value) = &item.cpu_stats {
+        check_counters!(
+            reasons,
+            "cpu_stats",
+            value,
+            [
+                interrupts,
+                ctx_switches,
+                soft_interrupts,
+                processes,
+                procs_running,
+                procs_blocked
+            ]
+        );
+    }
+    if let Some(value) = &item.cpu_times {
+        check_counters!(
+            reasons,
+            "cpu_times",
+            value,
+            [user, nice, system, idle, iowait, irq, softirq, steal, guest, guest_nice]
+        );
+    }
+    if let Some(value) = &item.load_avg {
+        for (name, load) in [
+            ("one", value.one),
+            ("five", value.five),
+            ("fifteen", value.fifteen),
+        ] {
+            if !load.is_finite() || load < 0.0 {
+                reasons.push(format!("load_avg.{} is not a positive number", name));
+            }
+        }
+    }
+    if let Some(value) = &item.memory {
+        check_counters!(
+            reasons,
+            "memory",
+            value,
+            [total, free, used, shared, buffers, cached]
+        );
+    }
+    if let Some(value) = &item.swap {
+        check_counters!(reasons, "swap", value, [total, free, used]);
+    }
+    for (idx, value) in item.disks.iter().flatten().enumerate() {
+        let prefix = format!("disks[{}]", idx);
+        check_len(reasons, &prefix, &value.name, MAX_VARCHAR_LEN);
+        check_len(reasons, &prefix, &value.mount_point, MAX_VARCHAR_LEN);
+        check_counters!(reasons, prefix, value, [total_space, avail_space]);
+        if value.avail_space > value.total_space {
+            reasons.push(format!(
+                "{}.avail_space is greater than total_space",
+                prefix
+            ));
+        }
+    }
+    for (idx, value) in item.ioblocks.iter().flatten().enumerate() {
+        let prefix = format!("ioblocks[{}]", idx);
+        check_len(reasons, &prefix, &value.device_name, MAX_VARCHAR_LEN);
+        check_counters!(
+            reasons,
+            prefix,
+            value,
+            [read_count, read_bytes, write_count, write_bytes, busy_time]
+        );
+    }
+    for (idx, value) in item.ionets.iter().flatten().enumerate() {
+        let prefix = format!("ionets[{}]", idx);
+        check_len(reasons, &prefix, &value.interface, MAX_VARCHAR_LEN);
+        check_counters!(
+            reasons,
+            prefix,
+            value,
+            [rx_bytes, rx_packets, rx_errs, rx_drop, tx_bytes, tx_packets, tx_errs, tx_drop]
+        );
+    }
+}
+
+/// Add the valid samples to the rows, the other ones are added to `rejected`
+pub fn push_valid_samples(
+    rows: &mut Rows,
+    rejected: &mut Vec<RejectedSample>,
+    uuid: &str,
+    batch: Option<&str>,
+    samples: &[IngestHost],
+) {
+    let now = chrono::Utc::now().naive_utc();
+
+    for (index, item) in samples.iter().enumerate() {
+        let reasons = check_sample(item, now);
+        if reasons.is_empty() {
+            item.push_rows(uuid, rows);
+        } else {
+            rejected.push(RejectedSample {
+                batch: batch.map(str::to_owned),
+                index,
+                created_at: item.host.created_at,
+                reasons,
+            });
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use chrono::NaiveDateTime;
+use serde_json::{Map, Value};
+use std::io::{BufRead, BufReader, Read};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::ingest::{check_future, valid_metric_name};
+use crate::models::{NewCustomMetric, Rows, RAW_RETENTION_DAYS};
+
+use super::{enqueue, resolve_prefixed};
+
+/// Idle connections are closed after this delay
+const READ_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// Lines are handed to the pipeline at least every this many lines
+const MAX_PENDING_LINES: usize = 5000;
+/// Longest line accepted, the connection is closed past it
+const MAX_LINE: usize = 4096;
+/// Upper bound of simultaneous connections (one thread each)
+const MAX_CONNECTIONS: usize = 256;
+
+static CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn spawn(binding: &str) -> std::io::Result<()> {
+    let listener = TcpListener::bind(binding)?;
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if CONNECTIONS.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                        CONNECTIONS.fetch_sub(1, Ordering::SeqCst);
+                        debug!("Graphite: too many connections, refusing one");
+                        continue;
+                    }
+                    std::thread::spawn(move || {
+                        handle_connection(stream);
+                        CONNECTIONS.fetch_sub(1, Ordering::SeqCst);
+                    });
+                }
+                Err(err) => error!("Graphite: cannot accept a connection: {}", err),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream) {
+    if let Err(err) = stream.set_read_timeout(Some(READ_TIMEOUT)) {
+        error!("Graphite: cannot set the read timeout: {}", err);
+        return;
+    }
+
+    let mut reader = BufReader::new(stream);
+    let mut rows = Rows::default();
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        match (&mut reader).take(MAX_LINE as u64 + 1).read_line(&mut line) {
+            Ok(0) => break,
+            Ok(len) if len > MAX_LINE && !line.ends_with('\n') => {
+                debug!("Graphite: closing connection: line too long");
+                break;
+            }
+            Ok(_) => {
+                let now = chrono::Utc::now().naive_utc();
+                match parse_line(line.trim(), now) {
+                    Some(metric) => rows.customs.push(metric),
+                    None => trace!("Graphite: skipping an invalid line"),
+                }
+            }
+            Err(err) => {
+                debug!("Graphite: closing connection: {}", err);
+                break;
+            }
+        }
+
+        // Write what we have as soon as the client stops sending
+        if reader.buffer().is_empty() || rows.customs.len() >= MAX_PENDING_LINES {
+            enqueue(std::mem::take(&mut rows));
+        }
+    }
+
+    enqueue(rows);
+}
+
+/// Date of a point, None if it's in the future or older than the raw retention
+fn created_at(timestamp: Option<&str>, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    // -1 (or no timestamp) means "now" for Graphite
+    let created_at = match timestamp.and_then(|ts| ts.parse::<i64>().ok()) {
+        Some(ts) if ts > 0 => chrono::DateTime::from_timestamp(ts, 0)?.naive_utc(),
+        _ => now,
+    };
+
+    let mut reasons = Vec::new();
+    check_future(&mut reasons, created_at, now);
+    if !reasons.is_empty() || created_at < now - chrono::Duration::days(RAW_RETENTION_DAYS) {
+        return None;
+    }
+
+    Some(created_at)
+}
+
+/// Parse `<token>.<path>[;tag=value...] <value> [<timestamp>]`
+fn parse_line(line: &str, now: NaiveDateTime) -> Option<NewCustomMetric> {
+    let mut parts = line.split_whitespace();
+    let (path, value) = (parts.next()?, parts.next()?);
+    let value: f64 = value.parse().ok().filter(|value: &f64| value.is_finite())?;
+
+    let created_at = created_at(parts.next(), now)?;
+
+    let mut tags = path.split(';');
+    let (host_uuid, name) = resolve_prefixed(tags.next()?)?;
+    if !valid_metric_name(name) {
+        return None;
+    }
+
+    let mut labels = Map::new();
+    for tag in tags {
+        let (key, value) = tag.split_once('=')?;
+        labels.insert(key.to_owned(), Value::String(value.to_owned()));
+    }
+
+    Some(NewCustomMetric {
+        name: name.to_owned(),
+        labels: Value::Object(labels),
+        value,
+        host_uuid,
+        created_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(ts: i64) -> NaiveDateTime {
+        chrono::DateTime::from_timestamp(ts, 0).unwrap().naive_utc()
+    }
+
+    #[test]
+    fn created_at_now() {
+        let now = date(1_700_000_000);
+        assert_eq!(created_at(None, now), Some(now));
+        assert_eq!(created_at(Some("-1"), now), Some(now));
+        assert_eq!(
+            created_at(Some("1699999990"), now),
+            Some(date(1_699_999_990))
+        );
+    }
+
+    #[test]
+    fn created_at_in_the_future() {
+        let now = date(1_700_000_000);
+        assert_eq!(
+            created_at(Some("1700000300"), now),
+            Some(date(1_700_000_300))
+        );
+        assert_eq!(created_at(Some("1700000301"), now), None);
+        assert_eq!(created_at(Some("4102444800"), now), None);
+    }
+
+    #[test]
+    fn created_at_past_the_retention() {
+        let now = date(1_700_000_000);
+        let oldest = 1_700_000_000 - RAW_RETENTION_DAYS * 24 * 3600;
+        assert_eq!(
+            created_at(Some(&oldest.to_string()), now),
+            Some(date(oldest))
+        );
+        assert_eq!(created_at(Some(&(oldest - 1).to_string()), now), None);
+        assert_eq!(created_at(Some("1"), now), None);
+    }
+}
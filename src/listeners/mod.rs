@@ -0,0 +1,101 @@
+//! StatsD (UDP) and Graphite plaintext (TCP) listeners, both optional.
+//! Neither protocol can carry the SPTK header, so the first component
+//! of each metric name must be the listener token of a host (see
+//! auth::signature), the rest being the name of the custom metric:
+//! `<token>.app.queue.depth:12|g` or `<token>.app.queue.depth 12 1700000000`
+//!
+//! The token is derived from the API key, which never travels in the
+//! metric names. Tokens are secrets as well: they are never logged.
+use moka::sync::Cache;
+use moka::Expiry;
+use once_cell::sync::Lazy;
+use std::time::{Duration, Instant};
+
+use crate::auth::signature::{fetch_host_key, parse_listener_token, verify_listener_tag};
+use crate::ingest::PIPELINE;
+use crate::models::Rows;
+use crate::utils::stats::STATS;
+use crate::{AUTHPOOL, CONFIG};
+
+mod graphite;
+mod statsd;
+
+/// Host uuid of each listener token, None for the invalid ones (cached as
+/// well, but shortly, so that a bad token doesn't hit the database each time).
+static LISTENERTOKENS_CACHE: Lazy<Cache<String, Option<String>>> = Lazy::new(|| {
+    Cache::builder()
+        .max_capacity(10_000)
+        .expire_after(TokenExpiry)
+        .build()
+});
+
+struct TokenExpiry;
+
+impl Expiry<String, Option<String>> for TokenExpiry {
+    fn expire_after_create(
+        &self,
+        _: &String,
+        uuid: &Option<String>,
+        _: Instant,
+    ) -> Option<Duration> {
+        match uuid {
+            Some(_) => Some(Duration::from_secs(60 * 5)),
+            None => Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+/// Split `<token>.<name>` and resolve the token to the uuid of its host.
+/// This may query the auth database, don't call it while holding a lock.
+fn resolve_prefixed(path: &str) -> Option<(String, &str)> {
+    let (token, name) = path.split_once('.')?;
+    let (uuid, tag) = parse_listener_token(token)?;
+
+    let uuid = LISTENERTOKENS_CACHE.get_with(token.to_owned(), || {
+        let mut conn = match AUTHPOOL.get() {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Listeners: cannot get a auth_db connection: {}", err);
+                return None;
+            }
+        };
+        match fetch_host_key(&mut conn, uuid) {
+            Ok(Some(key)) if verify_listener_tag(&key, uuid, &tag) => Some(uuid.to_owned()),
+            _ => None,
+        }
+    });
+
+    uuid.map(|uuid| (uuid, name))
+}
+
+/// Hand the rows to the pipeline, dropping them if it's full
+fn enqueue(rows: Rows) {
+    if rows.is_empty() {
+        return;
+    }
+
+    let count = rows.len();
+    if !PIPELINE.enqueue(rows) {
+        debug!("Listeners: pipeline full, dropping {} rows", count);
+        STATS.listener_dropped(count);
+    }
+}
+
+/// Start the listeners enabled in the config
+pub fn spawn_listeners() {
+    if let Some(binding) = &CONFIG.statsd_binding {
+        if let Err(err) = statsd::spawn(binding) {
+            error!("Listeners: cannot start StatsD on {}: {}", binding, err);
+            std::process::exit(1);
+        }
+        info!("StatsD listener started on {} (udp)", binding);
+    }
+
+    if let Some(binding) = &CONFIG.graphite_binding {
+        if let Err(err) = graphite::spawn(binding) {
+            error!("Listeners: cannot start Graphite on {}: {}", binding, err);
+            std::process::exit(1);
+        }
+        info!("Graphite listener started on {} (tcp)", binding);
+    }
+}
@@ -0,0 +1,213 @@
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::UdpSocket;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::ingest::valid_metric_name;
+use crate::models::{NewCustomMetric, Rows};
+use crate::CONFIG;
+
+use super::{enqueue, resolve_prefixed};
+
+/// Largest datagram we accept, StatsD clients stay well under it
+const MAX_DATAGRAM: usize = 64 * 1024;
+/// Upper bound of series aggregated between two flushes
+const MAX_SERIES: usize = 100_000;
+/// Percentile reported for the timers (as `<name>.p90`)
+const TIMER_PERCENTILE: f64 = 0.9;
+
+static AGGREGATOR: Lazy<Mutex<Aggregator>> = Lazy::new(|| Mutex::new(Aggregator::default()));
+
+/// host uuid, metric name and tags
+type SeriesKey = (String, String, BTreeMap<String, String>);
+
+#[derive(Debug)]
+enum Series {
+    Counter(f64),
+    Gauge(f64),
+    Timer(Vec<f64>),
+    Set(HashSet<String>),
+}
+
+#[derive(Debug, Default)]
+struct Aggregator {
+    series: HashMap<SeriesKey, Series>,
+    /// Last value of the gauges, kept across flushes for the relative updates
+    gauges: HashMap<SeriesKey, f64>,
+}
+
+impl Aggregator {
+    /// Add the `<value>|<type>[|@<rate>][|#<tags>]` part of a line, once
+    /// its `<token>.<name>` prefix is resolved to the host and metric name
+    fn add_line(&mut self, host_uuid: String, name: &str, rest: &str) -> Option<()> {
+        let mut fields = rest.split('|');
+        let raw = fields.next()?;
+        let kind = fields.next()?;
+
+        let (mut rate, mut tags) = (1.0, BTreeMap::new());
+        for field in fields {
+            if let Some(sample_rate) = field.strip_prefix('@') {
+                rate = sample_rate.parse().ok().filter(|rate| *rate > 0.0)?;
+            } else if let Some(list) = field.strip_prefix('#') {
+                for tag in list.split(',') {
+                    let (key, value) = tag.split_once(':').unwrap_or((tag, ""));
+                    tags.insert(key.to_owned(), value.to_owned());
+                }
+            }
+        }
+
+        if !valid_metric_name(name) {
+            return None;
+        }
+        let key = (host_uuid, name.to_owned(), tags);
+        if !self.series.contains_key(&key) && self.series.len() >= MAX_SERIES {
+            return None;
+        }
+
+        match kind {
+            "c" => {
+                let value: f64 = raw.parse().ok()?;
+                match self.series.entry(key).or_insert(Series::Counter(0.0)) {
+                    Series::Counter(total) => *total += value / rate,
+                    _ => return None,
+                }
+            }
+            "g" => {
+                let value: f64 = raw.parse().ok()?;
+                // "+x" and "-x" are relative to the previous value
+                let gauge = self.gauges.entry(key.clone()).or_insert(0.0);
+                if raw.starts_with('+') || raw.starts_with('-') {
+                    *gauge += value;
+                } else {
+                    *gauge = value;
+                }
+                self.series.insert(key, Series::Gauge(*gauge));
+            }
+            "ms" | "h" | "d" => {
+                let value: f64 = raw.parse().ok()?;
+                match self.series.entry(key).or_insert(Series::Timer(Vec::new())) {
+                    Series::Timer(values) => values.push(value),
+                    _ => return None,
+                }
+            }
+            "s" => match self
+                .series
+                .entry(key)
+                .or_insert(Series::Set(HashSet::new()))
+            {
+                Series::Set(values) => {
+                    values.insert(raw.to_owned());
+                }
+                _ => return None,
+            },
+            _ => return None,
+        }
+
+        Some(())
+    }
+
+    /// Turn the series aggregated since the last flush into rows
+    fn flush(&mut self) -> Rows {
+        let created_at = chrono::Utc::now().naive_utc();
+        let mut rows = Rows::default();
+
+        // Forget the gauges which are not updated anymore
+        if self.gauges.len() > MAX_SERIES {
+            self.gauges.clear();
+        }
+
+        for ((host_uuid, name, tags), series) in self.series.drain() {
+            let labels: Map<String, Value> = tags
+                .into_iter()
+                .map(|(key, value)| (key, Value::String(value)))
+                .collect();
+            let mut push = |name: String, value: f64| {
+                if valid_metric_name(&name) && value.is_finite() {
+                    rows.customs.push(NewCustomMetric {
+                        name,
+                        labels: Value::Object(labels.clone()),
+                        value,
+                        host_uuid: host_uuid.clone(),
+                        created_at,
+                    });
+                }
+            };
+
+            match series {
+                Series::Counter(value) | Series::Gauge(value) => push(name, value),
+                Series::Set(values) => push(name, values.len() as f64),
+                Series::Timer(mut values) => {
+                    if values.is_empty() {
+                        continue;
+                    }
+                    values.sort_by(f64::total_cmp);
+                    let count = values.len();
+                    let rank = ((count as f64 * TIMER_PERCENTILE).ceil() as usize).max(1);
+                    push(format!("{}.count", name), count as f64);
+                    push(
+                        format!("{}.mean", name),
+                        values.iter().sum::<f64>() / count as f64,
+                    );
+                    push(format!("{}.lower", name), values[0]);
+                    push(format!("{}.upper", name), values[count - 1]);
+                    push(format!("{}.p90", name), values[rank - 1]);
+                }
+            }
+        }
+
+        rows
+    }
+}
+
+pub fn spawn(binding: &str) -> std::io::Result<()> {
+    let socket = UdpSocket::bind(binding)?;
+
+    std::thread::spawn(move || {
+        let mut buf = vec![0u8; MAX_DATAGRAM];
+        loop {
+            let len = match socket.recv_from(&mut buf) {
+                Ok((len, _)) => len,
+                Err(err) => {
+                    error!("StatsD: cannot receive: {}", err);
+                    continue;
+                }
+            };
+
+            let Ok(packet) = std::str::from_utf8(&buf[..len]) else {
+                continue;
+            };
+            // Resolve the tokens before locking, it may query the database
+            let lines = packet
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .filter_map(|line| {
+                    let (path, rest) = line.split_once(':')?;
+                    let resolved = resolve_prefixed(path);
+                    if resolved.is_none() {
+                        trace!("StatsD: skipping a line with an invalid token");
+                    }
+                    Some((resolved?, rest))
+                })
+                .collect::<Vec<_>>();
+
+            let mut aggregator = AGGREGATOR.lock().unwrap();
+            for ((host_uuid, name), rest) in lines {
+                if aggregator.add_line(host_uuid, name, rest).is_none() {
+                    trace!("StatsD: skipping an invalid line for '{}'", name);
+                }
+            }
+        }
+    });
+
+    std::thread::spawn(|| loop {
+        std::thread::sleep(Duration::from_secs(CONFIG.statsd_flush_interval.max(1)));
+
+        let rows = AGGREGATOR.lock().unwrap().flush();
+        enqueue(rows);
+    });
+
+    Ok(())
+}
@@ -17,6 +17,9 @@ use crate::utils::config::Config;
 
 mod api;
 mod auth;
+mod ingest;
+mod listeners;
+mod models;
 mod routes;
 mod server;
 mod utils;
@@ -86,6 +89,13 @@ async fn main() -> std::io::Result<()> {
     // Apply the migrations to the database
     apply_migration(&METRICSPOOL);
 
+    // Start writing the queued rows and moving the staged samples to the queue
+    ingest::spawn_flusher(METRICSPOOL.clone());
+    ingest::spawn_sweeper();
+
+    // Start the StatsD/Graphite listeners (if enabled)
+    listeners::spawn_listeners();
+
     // Continue the initialization of the Actix web server
     server::server(METRICSPOOL.clone()).await
 }
@@ -0,0 +1,186 @@
+use chrono::{Duration, DurationRound, NaiveDateTime};
+use diesel::pg::PgConnection;
+use diesel::{sql_query, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use sproot::apierrors::ApiError;
+
+use super::Rows;
+
+/// Retention of the raw hypertables (see the add_retention_policy calls)
+pub const RAW_RETENTION_DAYS: i64 = 10;
+
+/// Continuous aggregates built on top of each raw table:
+/// (suffix, width of the buckets, retention of the view)
+const AGGREGATES: [(&str, Duration, Duration); 2] = [
+    ("10m", Duration::minutes(10), Duration::days(4)),
+    ("30m", Duration::minutes(30), Duration::days(30)),
+];
+
+/// Number of points the auto granularity wants at least over the range
+const AUTO_MIN_POINTS: i64 = 150;
+
+const TS_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Resolution the metrics are read at: the raw table, one of the views
+/// (see AGGREGATES) or the coarsest of those giving enough points (auto).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Granularity {
+    #[default]
+    #[serde(rename = "raw")]
+    Raw,
+    #[serde(rename = "10m")]
+    TenMinutes,
+    #[serde(rename = "30m")]
+    ThirtyMinutes,
+    #[serde(rename = "auto")]
+    Auto,
+}
+
+impl Granularity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Granularity::Raw => "raw",
+            Granularity::TenMinutes => AGGREGATES[0].0,
+            Granularity::ThirtyMinutes => AGGREGATES[1].0,
+            Granularity::Auto => "auto",
+        }
+    }
+
+    /// The view (suffix, bucket, retention) of the tier, None for raw and auto
+    fn aggregate(&self) -> Option<(&'static str, Duration, Duration)> {
+        match self {
+            Granularity::TenMinutes => Some(AGGREGATES[0]),
+            Granularity::ThirtyMinutes => Some(AGGREGATES[1]),
+            _ => None,
+        }
+    }
+
+    /// Resolve auto to the coarsest tier still covering min and giving at
+    /// least AUTO_MIN_POINTS points over [min, max], the finest tier covering
+    /// min if none does (the other granularities are returned as is).
+    pub fn resolve(self, min: NaiveDateTime, max: NaiveDateTime) -> Self {
+        if self != Granularity::Auto {
+            return self;
+        }
+
+        let now = chrono::Utc::now().naive_utc();
+        let covers = |tier: &&Granularity| match tier.aggregate() {
+            Some((_, _, retention)) => min >= now - retention,
+            None => min >= now - Duration::days(RAW_RETENTION_DAYS),
+        };
+        // The raw samples are (at least) as dense as the finest view
+        let points = |tier: &&Granularity| match tier.aggregate() {
+            Some((_, bucket, _)) => (max - min).num_seconds() / bucket.num_seconds(),
+            None => i64::MAX,
+        };
+
+        let tiers = [
+            Granularity::ThirtyMinutes,
+            Granularity::TenMinutes,
+            Granularity::Raw,
+        ];
+        tiers
+            .iter()
+            .filter(covers)
+            .find(|tier| points(tier) >= AUTO_MIN_POINTS)
+            .or_else(|| tiers.iter().rev().find(covers))
+            .copied()
+            .unwrap_or(Granularity::ThirtyMinutes)
+    }
+}
+
+/// Relation to read [min, max] of `table` from at the given granularity
+/// (the raw table or one of its views, see Granularity::resolve).
+pub fn dated_source(
+    table: &str,
+    granularity: Granularity,
+    min: NaiveDateTime,
+    max: NaiveDateTime,
+) -> String {
+    match granularity.resolve(min, max) {
+        Granularity::Raw => table.to_owned(),
+        tier => format!("{}_{}", table, tier.as_str()),
+    }
+}
+
+/// Time column of the source returned by dated_source: the raw tables
+/// have a created_at column, the views a time column.
+pub fn time_column(table: &str, source: &str) -> &'static str {
+    if source == table {
+        "created_at"
+    } else {
+        "time"
+    }
+}
+
+impl Rows {
+    /// Name of the tables having an aggregate which received rows,
+    /// along with the oldest and newest created_at of those rows.
+    pub fn aggregated_ranges(&self) -> Vec<(&'static str, NaiveDateTime, NaiveDateTime)> {
+        macro_rules! range {
+            ($ranges:expr, $name:literal, $rows:expr) => {
+                let min = $rows.iter().map(|row| row.created_at).min();
+                let max = $rows.iter().map(|row| row.created_at).max();
+                if let (Some(min), Some(max)) = (min, max) {
+                    $ranges.push(($name, min, max));
+                }
+            };
+        }
+
+        let mut ranges = Vec::new();
+        range!(ranges, "cputimes", self.cputimes);
+        range!(ranges, "cpustats", self.cpustats);
+        range!(ranges, "disks", self.disks);
+        range!(ranges, "ioblocks", self.ioblocks);
+        range!(ranges, "ionets", self.ionets);
+        range!(ranges, "loadavg", self.loadavg);
+        range!(ranges, "memory", self.memory);
+        range!(ranges, "swap", self.swap);
+        range!(ranges, "custom_metrics", self.customs);
+        range!(ranges, "containers", self.containers);
+        range!(ranges, "sensors", self.sensors);
+        range!(ranges, "smart", self.smart);
+        range!(ranges, "tcpstates", self.tcpstates);
+        range!(ranges, "kernelstats", self.kernelstats);
+        ranges
+    }
+}
+
+/// Refresh the continuous aggregates of `table` over [min, max].
+/// The window is aligned on the buckets of each view and clipped to
+/// its retention, refreshing older buckets would only resurrect them
+/// until the next run of the retention policy.
+pub fn refresh_aggregates(
+    conn: &mut PgConnection,
+    table: &str,
+    min: NaiveDateTime,
+    max: NaiveDateTime,
+) -> Result<(), ApiError> {
+    let now = chrono::Utc::now().naive_utc();
+
+    for (suffix, bucket, retention) in AGGREGATES {
+        let start = min
+            .max(now - retention)
+            .duration_trunc(bucket)
+            .unwrap_or(min);
+        let end = (max + bucket)
+            .duration_trunc(bucket)
+            .unwrap_or(max + bucket);
+        if start >= end {
+            continue;
+        }
+
+        // CALL can't be prepared with bind parameters, the timestamps
+        // are formatted by chrono so they're safe to inline.
+        sql_query(format!(
+            "CALL refresh_continuous_aggregate('{}_{}', '{}'::timestamp, '{}'::timestamp)",
+            table,
+            suffix,
+            start.format(TS_FORMAT),
+            end.format(TS_FORMAT)
+        ))
+        .execute(conn)?;
+    }
+
+    Ok(())
+}
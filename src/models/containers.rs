@@ -0,0 +1,98 @@
+use chrono::NaiveDateTime;
+use diesel::sql_types::{BigInt, Nullable, Text, Timestamp};
+use diesel::{sql_query, QueryableByName, RunQueryDsl};
+use serde::Serialize;
+use sproot::apierrors::ApiError;
+use sproot::ConnType;
+
+use super::{dated_source, time_column, Granularity};
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct Container {
+    #[diesel(sql_type = Text)]
+    pub container_id: String,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = BigInt)]
+    pub cpu_usage: i64,
+    #[diesel(sql_type = BigInt)]
+    pub memory_usage: i64,
+    #[diesel(sql_type = BigInt)]
+    pub memory_limit: i64,
+    #[diesel(sql_type = BigInt)]
+    pub read_bytes: i64,
+    #[diesel(sql_type = BigInt)]
+    pub write_bytes: i64,
+    #[diesel(sql_type = BigInt)]
+    pub rx_bytes: i64,
+    #[diesel(sql_type = BigInt)]
+    pub tx_bytes: i64,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct ContainerSeen {
+    #[diesel(sql_type = Text)]
+    pub container_id: String,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = Timestamp)]
+    pub first_seen: NaiveDateTime,
+    #[diesel(sql_type = Timestamp)]
+    pub last_seen: NaiveDateTime,
+}
+
+impl Container {
+    /// Get the containers metrics of a host between min_date and max_date,
+    /// optionally only those of `container_id`, at the given granularity.
+    pub fn get_dated(
+        conn: &mut ConnType,
+        uuid: &str,
+        container_id: Option<&str>,
+        granularity: Granularity,
+        min_date: NaiveDateTime,
+        max_date: NaiveDateTime,
+    ) -> Result<Vec<Self>, ApiError> {
+        let source = dated_source("containers", granularity, min_date, max_date);
+        let time = time_column("containers", &source);
+
+        Ok(sql_query(format!(
+            "SELECT container_id, name, cpu_usage, memory_usage, memory_limit, \
+            read_bytes, write_bytes, rx_bytes, tx_bytes, {time} as created_at FROM {source} \
+            WHERE host_uuid = $1 AND ($2 IS NULL OR container_id = $2) \
+            AND {time} BETWEEN $3 AND $4 \
+            ORDER BY {time}",
+        ))
+        .bind::<Text, _>(uuid)
+        .bind::<Nullable<Text>, _>(container_id)
+        .bind::<Timestamp, _>(min_date)
+        .bind::<Timestamp, _>(max_date)
+        .load(conn)?)
+    }
+}
+
+impl ContainerSeen {
+    /// Get the containers seen on a host between min_date and max_date
+    pub fn get_dated(
+        conn: &mut ConnType,
+        uuid: &str,
+        min_date: NaiveDateTime,
+        max_date: NaiveDateTime,
+    ) -> Result<Vec<Self>, ApiError> {
+        let source = dated_source("containers", Granularity::Auto, min_date, max_date);
+        let time = time_column("containers", &source);
+
+        Ok(sql_query(format!(
+            "SELECT container_id, name, min({time}) as first_seen, max({time}) as last_seen \
+            FROM {source} \
+            WHERE host_uuid = $1 AND {time} BETWEEN $2 AND $3 \
+            GROUP BY container_id, name \
+            ORDER BY name",
+        ))
+        .bind::<Text, _>(uuid)
+        .bind::<Timestamp, _>(min_date)
+        .bind::<Timestamp, _>(max_date)
+        .load(conn)?)
+    }
+}
@@ -0,0 +1,77 @@
+use chrono::NaiveDateTime;
+use diesel::sql_types::{Double, Jsonb, Nullable, Text, Timestamp};
+use diesel::{sql_query, QueryableByName, RunQueryDsl};
+use serde::Serialize;
+use sproot::apierrors::ApiError;
+use sproot::ConnType;
+
+use super::{dated_source, time_column, Granularity};
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct CustomMetric {
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = Jsonb)]
+    pub labels: serde_json::Value,
+    #[diesel(sql_type = Double)]
+    pub value: f64,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct CustomMetricName {
+    #[diesel(sql_type = Text)]
+    pub name: String,
+}
+
+impl CustomMetric {
+    /// Get the custom metrics of a host between min_date and max_date,
+    /// optionally only those named `name`, at the given granularity.
+    pub fn get_dated(
+        conn: &mut ConnType,
+        uuid: &str,
+        name: Option<&str>,
+        granularity: Granularity,
+        min_date: NaiveDateTime,
+        max_date: NaiveDateTime,
+    ) -> Result<Vec<Self>, ApiError> {
+        let source = dated_source("custom_metrics", granularity, min_date, max_date);
+        let time = time_column("custom_metrics", &source);
+
+        Ok(sql_query(format!(
+            "SELECT name, labels, value, {time} as created_at FROM {source} \
+            WHERE host_uuid = $1 AND ($2 IS NULL OR name = $2) \
+            AND {time} BETWEEN $3 AND $4 \
+            ORDER BY {time}",
+        ))
+        .bind::<Text, _>(uuid)
+        .bind::<Nullable<Text>, _>(name)
+        .bind::<Timestamp, _>(min_date)
+        .bind::<Timestamp, _>(max_date)
+        .load(conn)?)
+    }
+}
+
+impl CustomMetricName {
+    /// Get the distinct names of the custom metrics of a host between min_date and max_date
+    pub fn get_dated(
+        conn: &mut ConnType,
+        uuid: &str,
+        min_date: NaiveDateTime,
+        max_date: NaiveDateTime,
+    ) -> Result<Vec<Self>, ApiError> {
+        let source = dated_source("custom_metrics", Granularity::Auto, min_date, max_date);
+        let time = time_column("custom_metrics", &source);
+
+        Ok(sql_query(format!(
+            "SELECT DISTINCT name FROM {source} \
+            WHERE host_uuid = $1 AND {time} BETWEEN $2 AND $3 \
+            ORDER BY name",
+        ))
+        .bind::<Text, _>(uuid)
+        .bind::<Timestamp, _>(min_date)
+        .bind::<Timestamp, _>(max_date)
+        .load(conn)?)
+    }
+}
@@ -0,0 +1,174 @@
+use base64::Engine;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Bool, Bytea, Jsonb, Nullable, Text, Timestamp};
+use serde::{Serialize, Serializer};
+use sproot::apierrors::ApiError;
+use sproot::ConnType;
+
+use super::schema::dead_letters;
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = dead_letters)]
+pub struct NewDeadLetter {
+    /// None for the unverified letters (rejected by SptkValidator)
+    pub host_uuid: Option<String>,
+    /// Uuid of the query of the unverified letters, never trusted
+    pub claimed_uuid: Option<String>,
+    pub route: String,
+    pub reason: String,
+    /// Headers of the request, without the secrets
+    pub headers: serde_json::Value,
+    pub body: Vec<u8>,
+}
+
+/// A dead letter, without its body
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct DeadLetterEntry {
+    #[diesel(sql_type = BigInt)]
+    pub id: i64,
+    #[diesel(sql_type = Text)]
+    pub route: String,
+    #[diesel(sql_type = Text)]
+    pub reason: String,
+    /// False if the letter was rejected by SptkValidator (not replayable)
+    #[diesel(sql_type = Bool)]
+    pub verified: bool,
+    #[diesel(sql_type = BigInt)]
+    pub body_size: i64,
+    #[diesel(sql_type = Nullable<Timestamp>)]
+    pub replayed_at: Option<NaiveDateTime>,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct DeadLetter {
+    #[diesel(sql_type = BigInt)]
+    pub id: i64,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub host_uuid: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub claimed_uuid: Option<String>,
+    #[diesel(sql_type = Text)]
+    pub route: String,
+    #[diesel(sql_type = Text)]
+    pub reason: String,
+    #[diesel(sql_type = Jsonb)]
+    pub headers: serde_json::Value,
+    /// Base64 encoded in the responses
+    #[diesel(sql_type = Bytea)]
+    #[serde(serialize_with = "as_base64")]
+    pub body: Vec<u8>,
+    #[diesel(sql_type = Nullable<Timestamp>)]
+    pub replayed_at: Option<NaiveDateTime>,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+fn as_base64<S: Serializer>(body: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(body))
+}
+
+impl NewDeadLetter {
+    /// Insert the entry, dropping the oldest ones to keep at most `max` entries.
+    /// The unverified entries are bounded apart, so they can't evict the others.
+    pub fn insert(&self, conn: &mut ConnType, max: i64) -> Result<(), ApiError> {
+        diesel::insert_into(dead_letters::table)
+            .values(self)
+            .execute(conn)?;
+
+        sql_query(
+            "DELETE FROM dead_letters WHERE (host_uuid IS NULL) = $2 AND id <= \
+            (SELECT id FROM dead_letters WHERE (host_uuid IS NULL) = $2 \
+            ORDER BY id DESC OFFSET $1 LIMIT 1)",
+        )
+        .bind::<BigInt, _>(max)
+        .bind::<Bool, _>(self.host_uuid.is_none())
+        .execute(conn)?;
+
+        Ok(())
+    }
+}
+
+impl DeadLetterEntry {
+    /// Get the dead letters of a host (including the unverified ones
+    /// claiming to be from it), most recent first
+    pub fn get(
+        conn: &mut ConnType,
+        uuid: &str,
+        size: i64,
+        page: i64,
+    ) -> Result<Vec<Self>, ApiError> {
+        Ok(sql_query(
+            "SELECT id, route, reason, host_uuid IS NOT NULL as verified, \
+            length(body)::int8 as body_size, replayed_at, created_at \
+            FROM dead_letters WHERE host_uuid = $1 OR claimed_uuid = $1 \
+            ORDER BY id DESC LIMIT $2 OFFSET $3",
+        )
+        .bind::<Text, _>(uuid)
+        .bind::<BigInt, _>(size)
+        .bind::<BigInt, _>(page * size)
+        .load(conn)?)
+    }
+}
+
+impl DeadLetter {
+    /// Get a dead letter of the host (or claiming to be from it)
+    pub fn get_specific(conn: &mut ConnType, uuid: &str, id: i64) -> Result<Self, ApiError> {
+        let mut letters: Vec<Self> = sql_query(
+            "SELECT id, host_uuid, claimed_uuid, route, reason, headers, body, replayed_at, \
+            created_at FROM dead_letters WHERE id = $1 AND (host_uuid = $2 OR claimed_uuid = $2)",
+        )
+        .bind::<BigInt, _>(id)
+        .bind::<Text, _>(uuid)
+        .load(conn)?;
+
+        letters
+            .pop()
+            .ok_or_else(|| ApiError::NotFoundError(Some(format!("no dead letter {}", id))))
+    }
+
+    /// Mark the dead letter of the host as replayed, unless it already is.
+    /// Claiming it first ensures concurrent or repeated replays run only once.
+    /// The unverified letters are never claimed: their uuid is only what the
+    /// caller claimed, replaying them would ingest anyone's payload as the host's.
+    pub fn claim(conn: &mut ConnType, uuid: &str, id: i64) -> Result<Self, ApiError> {
+        let mut letters: Vec<Self> = sql_query(
+            "UPDATE dead_letters SET replayed_at = now() \
+            WHERE id = $1 AND host_uuid = $2 AND replayed_at IS NULL \
+            RETURNING id, host_uuid, claimed_uuid, route, reason, headers, body, replayed_at, \
+            created_at",
+        )
+        .bind::<BigInt, _>(id)
+        .bind::<Text, _>(uuid)
+        .load(conn)?;
+
+        match letters.pop() {
+            Some(letter) => Ok(letter),
+            None => {
+                let letter = Self::get_specific(conn, uuid, id)?;
+                if letter.host_uuid.is_none() {
+                    return Err(ApiError::InvalidRequestError(Some(format!(
+                        "dead letter {} is unverified (rejected by SptkValidator), it can't be replayed",
+                        id
+                    ))));
+                }
+                Err(ApiError::InvalidRequestError(Some(format!(
+                    "dead letter {} already replayed at {:?}",
+                    id, letter.replayed_at
+                ))))
+            }
+        }
+    }
+
+    /// Undo the claim of a dead letter whose replay failed
+    pub fn release(conn: &mut ConnType, id: i64) -> Result<(), ApiError> {
+        sql_query("UPDATE dead_letters SET replayed_at = NULL WHERE id = $1")
+            .bind::<BigInt, _>(id)
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,364 @@
+//! Server-side downsampling of the dated metrics, using Largest-Triangle-
+//! Three-Buckets: the first and last rows are kept and each bucket in
+//! between keeps the row forming the largest triangle with the previously
+//! kept row and the average of the next bucket. The rows are kept as is,
+//! so the responses don't change shape, only their number of rows does.
+//!
+//! Rows carry several values (user, system, ... for cputimes), the area of
+//! a triangle is summed over all of them, each value being scaled to its
+//! range over the series so the largest ones (bytes) don't hide the others.
+//! Tables holding several series (one per disk, interface, ...) are
+//! downsampled per series.
+use chrono::NaiveDateTime;
+use std::collections::BTreeMap;
+
+use super::{
+    Container, CpuStatsPoint, CpuTimesPoint, CustomMetric, DiskPoint, IoBlockPoint, IoNetPoint,
+    KernelStats, LoadAvgPoint, MemoryPoint, Sensor, Smart, SwapPoint, TcpStates,
+};
+
+/// Smallest max_points accepted (the first and last rows plus one bucket)
+pub const MIN_POINTS: usize = 3;
+
+pub trait Downsample {
+    fn time(&self) -> NaiveDateTime;
+    /// The values of the row, in the same order for every row
+    fn values(&self) -> Vec<f64>;
+    /// Key of the series the row belongs to (empty for single-series tables)
+    fn series(&self) -> String {
+        String::new()
+    }
+}
+
+/// Downsample each series of `rows` (sorted by time) to at most `max_points`
+/// rows, the rows are returned sorted by time.
+pub fn downsample<T: Downsample>(rows: Vec<T>, max_points: Option<usize>) -> Vec<T> {
+    let max_points = match max_points {
+        Some(max_points) if max_points >= MIN_POINTS => max_points,
+        _ => return rows,
+    };
+
+    let mut series: BTreeMap<String, Vec<T>> = BTreeMap::new();
+    for row in rows {
+        series.entry(row.series()).or_default().push(row);
+    }
+
+    let interleaved = series.len() > 1;
+    let mut rows = series
+        .into_values()
+        .flat_map(|rows| lttb(rows, max_points))
+        .collect::<Vec<_>>();
+    if interleaved {
+        rows.sort_by_key(|row| row.time());
+    }
+
+    rows
+}
+
+fn lttb<T: Downsample>(rows: Vec<T>, threshold: usize) -> Vec<T> {
+    let len = rows.len();
+    if len <= threshold {
+        return rows;
+    }
+
+    let xs = rows
+        .iter()
+        .map(|row| row.time().and_utc().timestamp_millis() as f64)
+        .collect::<Vec<_>>();
+    let mut ys = rows.iter().map(|row| row.values()).collect::<Vec<_>>();
+
+    // Scale each value to its range, constant values don't count
+    let width = ys.iter().map(Vec::len).min().unwrap_or(0);
+    for field in 0..width {
+        let min = ys.iter().map(|y| y[field]).fold(f64::INFINITY, f64::min);
+        let max = ys
+            .iter()
+            .map(|y| y[field])
+            .fold(f64::NEG_INFINITY, f64::max);
+        let range = max - min;
+        for y in ys.iter_mut() {
+            y[field] = if range > 0.0 {
+                (y[field] - min) / range
+            } else {
+                0.0
+            };
+        }
+    }
+
+    let every = (len - 2) as f64 / (threshold - 2) as f64;
+    let mut keep = vec![false; len];
+    keep[0] = true;
+    keep[len - 1] = true;
+
+    let mut prev = 0;
+    for bucket in 0..threshold - 2 {
+        // Average of the next bucket (the last row for the last bucket)
+        let next_start = (((bucket + 1) as f64 * every) as usize + 1).min(len - 1);
+        let next_end = (((bucket + 2) as f64 * every) as usize + 1).clamp(next_start + 1, len);
+        let next = next_start..next_end;
+        let count = next.len() as f64;
+        let avg_x = xs[next.clone()].iter().sum::<f64>() / count;
+        let avg_y = (0..width)
+            .map(|field| ys[next.clone()].iter().map(|y| y[field]).sum::<f64>() / count)
+            .collect::<Vec<_>>();
+
+        let start = (bucket as f64 * every) as usize + 1;
+        let end = ((bucket + 1) as f64 * every) as usize + 1;
+        let mut picked = start;
+        let mut max_area = -1.0;
+        for candidate in start..end.min(len - 1) {
+            let area = (0..width)
+                .map(|field| {
+                    ((xs[prev] - avg_x) * (ys[candidate][field] - ys[prev][field])
+                        - (xs[prev] - xs[candidate]) * (avg_y[field] - ys[prev][field]))
+                        .abs()
+                })
+                .sum::<f64>();
+            if area > max_area {
+                max_area = area;
+                picked = candidate;
+            }
+        }
+
+        keep[picked] = true;
+        prev = picked;
+    }
+
+    rows.into_iter()
+        .zip(keep)
+        .filter_map(|(row, keep)| keep.then_some(row))
+        .collect()
+}
+
+macro_rules! downsampled {
+    ($model:ident, [$($field:ident),*] $(, series: |$row:ident| $series:expr)?) => {
+        impl Downsample for $model {
+            fn time(&self) -> NaiveDateTime {
+                self.created_at
+            }
+
+            fn values(&self) -> Vec<f64> {
+                vec![$(self.$field.as_value()),*]
+            }
+
+            $(
+                fn series(&self) -> String {
+                    let $row = self;
+                    $series
+                }
+            )?
+        }
+    };
+}
+
+/// Numeric columns as the f64 used to compare the rows (missing ones as 0)
+trait AsValue {
+    fn as_value(&self) -> f64;
+}
+
+impl AsValue for i32 {
+    fn as_value(&self) -> f64 {
+        *self as f64
+    }
+}
+
+impl AsValue for i64 {
+    fn as_value(&self) -> f64 {
+        *self as f64
+    }
+}
+
+impl AsValue for f64 {
+    fn as_value(&self) -> f64 {
+        *self
+    }
+}
+
+impl<T: AsValue> AsValue for Option<T> {
+    fn as_value(&self) -> f64 {
+        self.as_ref().map(AsValue::as_value).unwrap_or_default()
+    }
+}
+
+downsampled!(
+    CpuTimesPoint,
+    [cuser, nice, system, idle, iowait, irq, softirq, steal, guest, guest_nice]
+);
+downsampled!(
+    CpuStatsPoint,
+    [
+        interrupts,
+        ctx_switches,
+        soft_interrupts,
+        processes,
+        procs_running,
+        procs_blocked
+    ]
+);
+downsampled!(LoadAvgPoint, [one, five, fifteen]);
+downsampled!(MemoryPoint, [total, free, used, shared, buffers, cached]);
+downsampled!(SwapPoint, [total, free, used]);
+downsampled!(DiskPoint, [total_space, avail_space], series: |row| row.disk_name.to_owned());
+downsampled!(
+    IoBlockPoint,
+    [read_count, read_bytes, write_count, write_bytes, busy_time],
+    series: |row| row.device_name.to_owned()
+);
+downsampled!(
+    IoNetPoint,
+    [rx_bytes, rx_packets, rx_errs, rx_drop, tx_bytes, tx_packets, tx_errs, tx_drop],
+    series: |row| row.interface.to_owned()
+);
+downsampled!(
+    Sensor,
+    [value, crit],
+    series: |row| format!("{}/{}", row.kind, row.label)
+);
+downsampled!(
+    Smart,
+    [reallocated_sectors, pending_sectors, wear_level, power_on_hours, failing],
+    series: |row| row.disk_name.to_owned()
+);
+downsampled!(
+    TcpStates,
+    [
+        established,
+        syn_sent,
+        syn_recv,
+        fin_wait1,
+        fin_wait2,
+        time_wait,
+        close,
+        close_wait,
+        last_ack,
+        listen,
+        closing
+    ]
+);
+downsampled!(
+    KernelStats,
+    [open_fds, max_fds, conntrack_count, conntrack_max, entropy]
+);
+downsampled!(
+    Container,
+    [cpu_usage, memory_usage, memory_limit, read_bytes, write_bytes, rx_bytes, tx_bytes],
+    series: |row| row.container_id.to_owned()
+);
+downsampled!(
+    CustomMetric,
+    [value],
+    series: |row| format!("{}{}", row.name, row.labels)
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Row {
+        at: i64,
+        value: f64,
+        series: &'static str,
+    }
+
+    impl Downsample for Row {
+        fn time(&self) -> NaiveDateTime {
+            chrono::DateTime::from_timestamp(self.at, 0)
+                .unwrap()
+                .naive_utc()
+        }
+
+        fn values(&self) -> Vec<f64> {
+            vec![self.value]
+        }
+
+        fn series(&self) -> String {
+            self.series.to_owned()
+        }
+    }
+
+    fn rows(values: &[f64]) -> Vec<Row> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(at, value)| Row {
+                at: at as i64,
+                value: *value,
+                series: "",
+            })
+            .collect()
+    }
+
+    fn times(rows: &[Row]) -> Vec<i64> {
+        rows.iter().map(|row| row.at).collect()
+    }
+
+    #[test]
+    fn lttb_keeps_fewer_rows_than_threshold() {
+        let kept = lttb(rows(&[1.0, 5.0, 2.0]), 5);
+        assert_eq!(times(&kept), vec![0, 1, 2]);
+
+        let kept = lttb(rows(&[1.0, 5.0, 2.0, 4.0, 3.0]), 5);
+        assert_eq!(times(&kept), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn lttb_keeps_the_spike() {
+        let mut values = vec![0.0; 10];
+        values[4] = 10.0;
+
+        let kept = lttb(rows(&values), 3);
+        assert_eq!(times(&kept), vec![0, 4, 9]);
+    }
+
+    #[test]
+    fn lttb_picks_one_row_per_bucket() {
+        let values = (0..100).map(|i| (i * 37 % 11) as f64).collect::<Vec<_>>();
+        let threshold = 10;
+
+        let kept = times(&lttb(rows(&values), threshold));
+        assert_eq!(kept.len(), threshold);
+        assert_eq!(kept[0], 0);
+        assert_eq!(kept[threshold - 1], 99);
+
+        // 98 rows between the first and last ones, in 8 buckets of 12.25 rows
+        let every = 98.0 / 8.0;
+        for (bucket, at) in kept[1..threshold - 1].iter().enumerate() {
+            let start = (bucket as f64 * every) as i64 + 1;
+            let end = ((bucket + 1) as f64 * every) as i64 + 1;
+            assert!(
+                (start..end).contains(at),
+                "row {} out of bucket {} [{}, {})",
+                at,
+                bucket,
+                start,
+                end
+            );
+        }
+    }
+
+    #[test]
+    fn downsample_interleaved_series() {
+        let rows = (0..20)
+            .map(|at| Row {
+                at,
+                // A spike per series: at 8 for "a", at 13 for "b"
+                value: if at == 8 || at == 13 { 10.0 } else { 0.0 },
+                series: if at % 2 == 0 { "a" } else { "b" },
+            })
+            .collect::<Vec<_>>();
+
+        let kept = downsample(rows, Some(3));
+        assert_eq!(times(&kept), vec![0, 1, 8, 13, 18, 19]);
+        assert_eq!(kept.iter().filter(|row| row.series == "a").count(), 3);
+        assert_eq!(kept.iter().filter(|row| row.series == "b").count(), 3);
+    }
+
+    #[test]
+    fn downsample_without_max_points() {
+        let kept = downsample(rows(&[1.0, 2.0, 3.0, 4.0]), None);
+        assert_eq!(times(&kept), vec![0, 1, 2, 3]);
+
+        let kept = downsample(rows(&[1.0, 2.0, 3.0, 4.0]), Some(MIN_POINTS - 1));
+        assert_eq!(times(&kept), vec![0, 1, 2, 3]);
+    }
+}
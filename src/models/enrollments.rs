@@ -0,0 +1,26 @@
+use diesel::prelude::*;
+use sproot::apierrors::ApiError;
+use sproot::ConnType;
+use uuid::Uuid;
+
+use super::schema::enrollments;
+
+#[derive(Insertable, Debug, Clone)]
+#[diesel(table_name = enrollments)]
+pub struct NewEnrollment {
+    pub host_uuid: String,
+    pub key_id: i64,
+    pub customer_id: Uuid,
+    pub berta: String,
+    pub remote_addr: Option<String>,
+}
+
+impl NewEnrollment {
+    pub fn insert(&self, conn: &mut ConnType) -> Result<(), ApiError> {
+        diesel::insert_into(enrollments::table)
+            .values(self)
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
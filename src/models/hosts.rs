@@ -0,0 +1,28 @@
+use diesel::{QueryDsl, RunQueryDsl};
+use serde::Serialize;
+use sproot::apierrors::ApiError;
+use sproot::models::Host;
+use sproot::ConnType;
+
+use super::schema::hosts;
+
+/// A Host along with the columns sproot doesn't know about
+#[derive(Debug, Serialize)]
+pub struct HostDetails {
+    #[serde(flatten)]
+    pub host: Host,
+    /// Skew of the host's clock in ms (positive if ahead), None until measured
+    pub clock_skew: Option<i64>,
+}
+
+impl HostDetails {
+    pub fn get_specific(conn: &mut ConnType, uuid: &str) -> Result<Self, ApiError> {
+        let host = Host::get_specific(conn, uuid)?;
+        let clock_skew = hosts::table
+            .find(uuid)
+            .select(hosts::clock_skew)
+            .first::<Option<i64>>(conn)?;
+
+        Ok(Self { host, clock_skew })
+    }
+}
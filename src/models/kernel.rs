@@ -0,0 +1,100 @@
+use chrono::NaiveDateTime;
+use diesel::sql_types::{BigInt, Text, Timestamp};
+use diesel::{sql_query, QueryableByName, RunQueryDsl};
+use serde::Serialize;
+use sproot::apierrors::ApiError;
+use sproot::ConnType;
+
+use super::{dated_source, time_column, Granularity};
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct TcpStates {
+    #[diesel(sql_type = BigInt)]
+    pub established: i64,
+    #[diesel(sql_type = BigInt)]
+    pub syn_sent: i64,
+    #[diesel(sql_type = BigInt)]
+    pub syn_recv: i64,
+    #[diesel(sql_type = BigInt)]
+    pub fin_wait1: i64,
+    #[diesel(sql_type = BigInt)]
+    pub fin_wait2: i64,
+    #[diesel(sql_type = BigInt)]
+    pub time_wait: i64,
+    #[diesel(sql_type = BigInt)]
+    pub close: i64,
+    #[diesel(sql_type = BigInt)]
+    pub close_wait: i64,
+    #[diesel(sql_type = BigInt)]
+    pub last_ack: i64,
+    #[diesel(sql_type = BigInt)]
+    pub listen: i64,
+    #[diesel(sql_type = BigInt)]
+    pub closing: i64,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct KernelStats {
+    #[diesel(sql_type = BigInt)]
+    pub open_fds: i64,
+    #[diesel(sql_type = BigInt)]
+    pub max_fds: i64,
+    #[diesel(sql_type = BigInt)]
+    pub conntrack_count: i64,
+    #[diesel(sql_type = BigInt)]
+    pub conntrack_max: i64,
+    #[diesel(sql_type = BigInt)]
+    pub entropy: i64,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+impl TcpStates {
+    /// Get the count of TCP sockets per state of a host between min_date and max_date
+    pub fn get_dated(
+        conn: &mut ConnType,
+        uuid: &str,
+        granularity: Granularity,
+        min_date: NaiveDateTime,
+        max_date: NaiveDateTime,
+    ) -> Result<Vec<Self>, ApiError> {
+        let source = dated_source("tcpstates", granularity, min_date, max_date);
+        let time = time_column("tcpstates", &source);
+
+        Ok(sql_query(format!(
+            "SELECT established, syn_sent, syn_recv, fin_wait1, fin_wait2, time_wait, close, close_wait, last_ack, listen, closing, {time} as created_at FROM {source} \
+            WHERE host_uuid = $1 AND {time} BETWEEN $2 AND $3 \
+            ORDER BY {time}",
+        ))
+        .bind::<Text, _>(uuid)
+        .bind::<Timestamp, _>(min_date)
+        .bind::<Timestamp, _>(max_date)
+        .load(conn)?)
+    }
+}
+
+impl KernelStats {
+    /// Get the kernel resources usage of a host between min_date and max_date
+    pub fn get_dated(
+        conn: &mut ConnType,
+        uuid: &str,
+        granularity: Granularity,
+        min_date: NaiveDateTime,
+        max_date: NaiveDateTime,
+    ) -> Result<Vec<Self>, ApiError> {
+        let source = dated_source("kernelstats", granularity, min_date, max_date);
+        let time = time_column("kernelstats", &source);
+
+        Ok(sql_query(format!(
+            "SELECT open_fds, max_fds, conntrack_count, conntrack_max, entropy, {time} as created_at FROM {source} \
+            WHERE host_uuid = $1 AND {time} BETWEEN $2 AND $3 \
+            ORDER BY {time}",
+        ))
+        .bind::<Text, _>(uuid)
+        .bind::<Timestamp, _>(min_date)
+        .bind::<Timestamp, _>(max_date)
+        .load(conn)?)
+    }
+}
@@ -0,0 +1,227 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::schema::*;
+
+/// Columns of the hosts row we know about from an ingest.
+/// Fields left to None keep their current value in the database.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HostUpsert {
+    pub uuid: String,
+    pub system: Option<String>,
+    pub os_version: Option<String>,
+    pub hostname: Option<String>,
+    pub uptime: Option<i64>,
+    /// Skew of the host's clock in ms, as measured on ingest
+    pub clock_skew: Option<i64>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, Default, Serialize, Deserialize)]
+#[diesel(table_name = cputimes)]
+pub struct NewCpuTimes {
+    pub cuser: i64,
+    pub nice: i64,
+    pub system: i64,
+    pub idle: i64,
+    pub iowait: i64,
+    pub irq: i64,
+    pub softirq: i64,
+    pub steal: i64,
+    pub guest: i64,
+    pub guest_nice: i64,
+    pub host_uuid: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, Default, Serialize, Deserialize)]
+#[diesel(table_name = cpustats)]
+pub struct NewCpuStats {
+    pub interrupts: i64,
+    pub ctx_switches: i64,
+    pub soft_interrupts: i64,
+    pub processes: i64,
+    pub procs_running: i64,
+    pub procs_blocked: i64,
+    pub host_uuid: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, Default, Serialize, Deserialize)]
+#[diesel(table_name = disks)]
+pub struct NewDisk {
+    pub disk_name: String,
+    pub mount_point: String,
+    pub total_space: i64,
+    pub avail_space: i64,
+    pub host_uuid: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, Default, Serialize, Deserialize)]
+#[diesel(table_name = ioblocks)]
+pub struct NewIoBlock {
+    pub device_name: String,
+    pub read_count: i64,
+    pub read_bytes: i64,
+    pub write_count: i64,
+    pub write_bytes: i64,
+    pub busy_time: i64,
+    pub host_uuid: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, Default, Serialize, Deserialize)]
+#[diesel(table_name = ionets)]
+pub struct NewIoNet {
+    pub interface: String,
+    pub rx_bytes: i64,
+    pub rx_packets: i64,
+    pub rx_errs: i64,
+    pub rx_drop: i64,
+    pub tx_bytes: i64,
+    pub tx_packets: i64,
+    pub tx_errs: i64,
+    pub tx_drop: i64,
+    pub host_uuid: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, Default, Serialize, Deserialize)]
+#[diesel(table_name = loadavg)]
+pub struct NewLoadAvg {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+    pub host_uuid: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, Default, Serialize, Deserialize)]
+#[diesel(table_name = memory)]
+pub struct NewMemory {
+    pub total: i64,
+    pub free: i64,
+    pub used: i64,
+    pub shared: i64,
+    pub buffers: i64,
+    pub cached: i64,
+    pub host_uuid: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, Default, Serialize, Deserialize)]
+#[diesel(table_name = swap)]
+pub struct NewSwap {
+    pub total: i64,
+    pub free: i64,
+    pub used: i64,
+    pub host_uuid: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, Default, Serialize, Deserialize)]
+#[diesel(table_name = custom_metrics)]
+pub struct NewCustomMetric {
+    pub name: String,
+    pub labels: serde_json::Value,
+    pub value: f64,
+    pub host_uuid: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, Default, Serialize, Deserialize)]
+#[diesel(table_name = processes)]
+pub struct NewProcess {
+    pub pid: i32,
+    pub name: String,
+    pub cmdline_hash: String,
+    pub cpu: f64,
+    pub rss: i64,
+    pub read_bytes: i64,
+    pub write_bytes: i64,
+    pub host_uuid: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, Default, Serialize, Deserialize)]
+#[diesel(table_name = containers)]
+pub struct NewContainer {
+    pub container_id: String,
+    pub name: String,
+    pub cpu_usage: i64,
+    pub memory_usage: i64,
+    pub memory_limit: i64,
+    pub read_bytes: i64,
+    pub write_bytes: i64,
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+    pub host_uuid: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, Default, Serialize, Deserialize)]
+#[diesel(table_name = sensors)]
+pub struct NewSensor {
+    pub label: String,
+    pub kind: String,
+    pub value: f64,
+    pub crit: Option<f64>,
+    pub host_uuid: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, Default, Serialize, Deserialize)]
+#[diesel(table_name = smart)]
+pub struct NewSmart {
+    pub disk_name: String,
+    pub reallocated_sectors: i64,
+    pub pending_sectors: i64,
+    pub wear_level: Option<i64>,
+    pub power_on_hours: i64,
+    pub failing: i32,
+    pub host_uuid: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, Default, Serialize, Deserialize)]
+#[diesel(table_name = tcpstates)]
+pub struct NewTcpStates {
+    pub established: i64,
+    pub syn_sent: i64,
+    pub syn_recv: i64,
+    pub fin_wait1: i64,
+    pub fin_wait2: i64,
+    pub time_wait: i64,
+    pub close: i64,
+    pub close_wait: i64,
+    pub last_ack: i64,
+    pub listen: i64,
+    pub closing: i64,
+    pub host_uuid: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug, Clone, Default, Serialize, Deserialize)]
+#[diesel(table_name = kernelstats)]
+pub struct NewKernelStats {
+    pub open_fds: i64,
+    pub max_fds: i64,
+    pub conntrack_count: i64,
+    pub conntrack_max: i64,
+    pub entropy: i64,
+    pub host_uuid: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// State of a systemd unit as reported by a host, applied to the units
+/// table (and unit_transitions if it changed) by Rows::insert.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UnitUpsert {
+    pub unit: String,
+    pub state: String,
+    pub restarts: i64,
+    pub host_uuid: String,
+    pub created_at: NaiveDateTime,
+}
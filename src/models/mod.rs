@@ -0,0 +1,459 @@
+//! The read side of the metrics tables lives in sproot, but the
+//! receivers (Prometheus, ...) produce rows which don't go through
+//! an HttpHost, and the ingest pipeline merges the rows of many hosts
+//! before writing them. This mod holds the insertable version of those
+//! rows and the logic to write them in bulk.
+use diesel::pg::PgConnection;
+use diesel::sql_types::{Array, BigInt, Nullable, Text, Timestamp};
+use diesel::{sql_query, Connection, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use sproot::apierrors::ApiError;
+use sproot::models::HttpHost;
+use std::collections::HashMap;
+
+mod aggregates;
+mod containers;
+mod custom;
+mod dead_letters;
+mod downsample;
+mod enrollments;
+mod hosts;
+mod kernel;
+mod metrics;
+mod processes;
+mod rates;
+pub mod schema;
+mod sensors;
+mod system;
+mod units;
+
+pub use aggregates::*;
+pub use containers::*;
+pub use custom::*;
+pub use dead_letters::*;
+pub use downsample::*;
+pub use enrollments::*;
+pub use hosts::*;
+pub use kernel::*;
+pub use metrics::*;
+pub use processes::*;
+pub use rates::*;
+pub use sensors::*;
+pub use system::*;
+pub use units::*;
+
+/// Maximum number of rows per INSERT statement. Postgres caps the number
+/// of bind parameters to 65535 and our widest table has 12 columns.
+const INSERT_CHUNK: usize = 4000;
+
+macro_rules! insert_chunked {
+    ($conn:expr, $table:expr, $rows:expr) => {
+        for chunk in $rows.chunks(INSERT_CHUNK) {
+            diesel::insert_into($table).values(chunk).execute($conn)?;
+        }
+    };
+}
+
+/// A set of rows, grouped by destination table, ready to be inserted.
+/// Serializable so a batch which couldn't be written can be dead lettered.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Rows {
+    pub hosts: Vec<HostUpsert>,
+    pub cputimes: Vec<NewCpuTimes>,
+    pub cpustats: Vec<NewCpuStats>,
+    pub disks: Vec<NewDisk>,
+    pub ioblocks: Vec<NewIoBlock>,
+    pub ionets: Vec<NewIoNet>,
+    pub loadavg: Vec<NewLoadAvg>,
+    pub memory: Vec<NewMemory>,
+    pub swap: Vec<NewSwap>,
+    pub customs: Vec<NewCustomMetric>,
+    pub processes: Vec<NewProcess>,
+    pub containers: Vec<NewContainer>,
+    pub sensors: Vec<NewSensor>,
+    pub smart: Vec<NewSmart>,
+    pub tcpstates: Vec<NewTcpStates>,
+    pub kernelstats: Vec<NewKernelStats>,
+    pub units: Vec<UnitUpsert>,
+    /// Batches (host uuid, batch id) claimed for these rows, released if
+    /// the rows can't be written so the agent's retries aren't dropped
+    #[serde(skip)]
+    pub batches: Vec<(String, String)>,
+}
+
+impl Rows {
+    /// Number of metrics rows (hosts excluded)
+    pub fn len(&self) -> usize {
+        self.cputimes.len()
+            + self.cpustats.len()
+            + self.disks.len()
+            + self.ioblocks.len()
+            + self.ionets.len()
+            + self.loadavg.len()
+            + self.memory.len()
+            + self.swap.len()
+            + self.customs.len()
+            + self.processes.len()
+            + self.containers.len()
+            + self.sensors.len()
+            + self.smart.len()
+            + self.tcpstates.len()
+            + self.kernelstats.len()
+            + self.units.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty() && self.len() == 0
+    }
+
+    /// Move all the rows of `other` into self
+    pub fn append(&mut self, mut other: Rows) {
+        self.hosts.append(&mut other.hosts);
+        self.cputimes.append(&mut other.cputimes);
+        self.cpustats.append(&mut other.cpustats);
+        self.disks.append(&mut other.disks);
+        self.ioblocks.append(&mut other.ioblocks);
+        self.ionets.append(&mut other.ionets);
+        self.loadavg.append(&mut other.loadavg);
+        self.memory.append(&mut other.memory);
+        self.swap.append(&mut other.swap);
+        self.customs.append(&mut other.customs);
+        self.processes.append(&mut other.processes);
+        self.containers.append(&mut other.containers);
+        self.sensors.append(&mut other.sensors);
+        self.smart.append(&mut other.smart);
+        self.tcpstates.append(&mut other.tcpstates);
+        self.kernelstats.append(&mut other.kernelstats);
+        self.units.append(&mut other.units);
+        self.batches.append(&mut other.batches);
+    }
+
+    /// Split the rows by host
+    pub fn split_by_host(self) -> HashMap<String, Rows> {
+        let mut hosts: HashMap<String, Rows> = HashMap::new();
+
+        for row in self.hosts {
+            hosts.entry(row.uuid.clone()).or_default().hosts.push(row);
+        }
+        for batch in self.batches {
+            hosts
+                .entry(batch.0.clone())
+                .or_default()
+                .batches
+                .push(batch);
+        }
+        macro_rules! split {
+            ($($field:ident),*) => {
+                $(
+                    for row in self.$field {
+                        hosts.entry(row.host_uuid.clone()).or_default().$field.push(row);
+                    }
+                )*
+            };
+        }
+        split!(
+            cputimes,
+            cpustats,
+            disks,
+            ioblocks,
+            ionets,
+            loadavg,
+            memory,
+            swap,
+            customs,
+            processes,
+            containers,
+            sensors,
+            smart,
+            tcpstates,
+            kernelstats,
+            units
+        );
+
+        hosts
+    }
+
+    /// Add the rows carried by an HttpHost sent by one of our agents
+    pub fn push_host(&mut self, uuid: &str, item: &HttpHost) {
+        let (host_uuid, created_at) = (uuid.to_owned(), item.created_at);
+
+        self.hosts.push(HostUpsert {
+            uuid: host_uuid.clone(),
+            system: Some(item.system.to_owned()),
+            os_version: Some(item.os_version.to_owned()),
+            hostname: Some(item.hostname.to_owned()),
+            uptime: Some(item.uptime),
+            clock_skew: None,
+            created_at,
+        });
+
+        if let Some(value) = &item.cpu_stats {
+            self.cpustats.push(NewCpuStats {
+                interrupts: value.interrupts,
+                ctx_switches: value.ctx_switches,
+                soft_interrupts: value.soft_interrupts,
+                processes: value.processes,
+                procs_running: value.procs_running,
+                procs_blocked: value.procs_blocked,
+                host_uuid: host_uuid.clone(),
+                created_at,
+            });
+        }
+        if let Some(value) = &item.cpu_times {
+            self.cputimes.push(NewCpuTimes {
+                cuser: value.user,
+                nice: value.nice,
+                system: value.system,
+                idle: value.idle,
+                iowait: value.iowait,
+                irq: value.irq,
+                softirq: value.softirq,
+                steal: value.steal,
+                guest: value.guest,
+                guest_nice: value.guest_nice,
+                host_uuid: host_uuid.clone(),
+                created_at,
+            });
+        }
+        if let Some(value) = &item.load_avg {
+            self.loadavg.push(NewLoadAvg {
+                one: value.one,
+                five: value.five,
+                fifteen: value.fifteen,
+                host_uuid: host_uuid.clone(),
+                created_at,
+            });
+        }
+        if let Some(value) = &item.memory {
+            self.memory.push(NewMemory {
+                total: value.total,
+                free: value.free,
+                used: value.used,
+                shared: value.shared,
+                buffers: value.buffers,
+                cached: value.cached,
+                host_uuid: host_uuid.clone(),
+                created_at,
+            });
+        }
+        if let Some(value) = &item.swap {
+            self.swap.push(NewSwap {
+                total: value.total,
+                free: value.free,
+                used: value.used,
+                host_uuid: host_uuid.clone(),
+                created_at,
+            });
+        }
+        for value in item.disks.iter().flatten() {
+            self.disks.push(NewDisk {
+                disk_name: value.name.to_owned(),
+                mount_point: value.mount_point.to_owned(),
+                total_space: value.total_space,
+                avail_space: value.avail_space,
+                host_uuid: host_uuid.clone(),
+                created_at,
+            });
+        }
+        for value in item.ioblocks.iter().flatten() {
+            self.ioblocks.push(NewIoBlock {
+                device_name: value.device_name.to_owned(),
+                read_count: value.read_count,
+                read_bytes: value.read_bytes,
+                write_count: value.write_count,
+                write_bytes: value.write_bytes,
+                busy_time: value.busy_time,
+                host_uuid: host_uuid.clone(),
+                created_at,
+            });
+        }
+        for value in item.ionets.iter().flatten() {
+            self.ionets.push(NewIoNet {
+                interface: value.interface.to_owned(),
+                rx_bytes: value.rx_bytes,
+                rx_packets: value.rx_packets,
+                rx_errs: value.rx_errs,
+                rx_drop: value.rx_drop,
+                tx_bytes: value.tx_bytes,
+                tx_packets: value.tx_packets,
+                tx_errs: value.tx_errs,
+                tx_drop: value.tx_drop,
+                host_uuid: host_uuid.clone(),
+                created_at,
+            });
+        }
+    }
+
+    /// Insert all the rows inside a single transaction
+    pub fn insert(&self, conn: &mut PgConnection) -> Result<(), ApiError> {
+        conn.transaction::<_, ApiError, _>(|conn| {
+            upsert_hosts(conn, &self.hosts)?;
+
+            insert_chunked!(conn, schema::cputimes::table, self.cputimes);
+            insert_chunked!(conn, schema::cpustats::table, self.cpustats);
+            insert_chunked!(conn, schema::disks::table, self.disks);
+            insert_chunked!(conn, schema::ioblocks::table, self.ioblocks);
+            insert_chunked!(conn, schema::ionets::table, self.ionets);
+            insert_chunked!(conn, schema::loadavg::table, self.loadavg);
+            insert_chunked!(conn, schema::memory::table, self.memory);
+            insert_chunked!(conn, schema::swap::table, self.swap);
+            insert_chunked!(conn, schema::custom_metrics::table, self.customs);
+            insert_chunked!(conn, schema::processes::table, self.processes);
+            insert_chunked!(conn, schema::containers::table, self.containers);
+            insert_chunked!(conn, schema::sensors::table, self.sensors);
+            insert_chunked!(conn, schema::smart::table, self.smart);
+            insert_chunked!(conn, schema::tcpstates::table, self.tcpstates);
+            insert_chunked!(conn, schema::kernelstats::table, self.kernelstats);
+
+            upsert_units(conn, &self.units)?;
+
+            Ok(())
+        })
+    }
+}
+
+/// Create or update the hosts rows. Unknown fields (None) don't
+/// overwrite the value already present in the database.
+/// The samples are merged per host first (the latest known value of each
+/// field wins), then written at once: the new hosts are inserted and the
+/// rows of all of them updated.
+fn upsert_hosts(conn: &mut PgConnection, hosts: &[HostUpsert]) -> Result<(), ApiError> {
+    if hosts.is_empty() {
+        return Ok(());
+    }
+
+    let mut sorted: Vec<&HostUpsert> = hosts.iter().collect();
+    sorted.sort_by_key(|host| host.created_at);
+    let mut merged: HashMap<&str, HostUpsert> = HashMap::new();
+    for host in sorted {
+        match merged.get_mut(host.uuid.as_str()) {
+            Some(known) => {
+                known.system = host.system.clone().or(known.system.take());
+                known.os_version = host.os_version.clone().or(known.os_version.take());
+                known.hostname = host.hostname.clone().or(known.hostname.take());
+                known.uptime = host.uptime.or(known.uptime);
+                known.clock_skew = host.clock_skew.or(known.clock_skew);
+            }
+            None => {
+                merged.insert(&host.uuid, host.clone());
+            }
+        }
+    }
+    let merged: Vec<HostUpsert> = merged.into_values().collect();
+
+    let systems: Vec<_> = merged.iter().map(|host| host.system.clone()).collect();
+    let os_versions: Vec<_> = merged.iter().map(|host| host.os_version.clone()).collect();
+    let hostnames: Vec<_> = merged.iter().map(|host| host.hostname.clone()).collect();
+    let uptimes: Vec<_> = merged.iter().map(|host| host.uptime).collect();
+    let uuids: Vec<_> = merged.iter().map(|host| host.uuid.clone()).collect();
+    let created_ats: Vec<_> = merged.iter().map(|host| host.created_at).collect();
+    let clock_skews: Vec<_> = merged.iter().map(|host| host.clock_skew).collect();
+
+    let columns = "unnest($1::text[], $2::text[], $3::text[], $4::int8[], $5::text[], \
+        $6::timestamp[], $7::int8[]) \
+        AS t(system, os_version, hostname, uptime, uuid, created_at, clock_skew)";
+    macro_rules! bind_columns {
+        ($query:expr) => {
+            $query
+                .bind::<Array<Nullable<Text>>, _>(&systems)
+                .bind::<Array<Nullable<Text>>, _>(&os_versions)
+                .bind::<Array<Nullable<Text>>, _>(&hostnames)
+                .bind::<Array<Nullable<BigInt>>, _>(&uptimes)
+                .bind::<Array<Text>, _>(&uuids)
+                .bind::<Array<Timestamp>, _>(&created_ats)
+                .bind::<Array<Nullable<BigInt>>, _>(&clock_skews)
+        };
+    }
+
+    bind_columns!(sql_query(format!(
+        "INSERT INTO hosts (system, os_version, hostname, uptime, uuid, created_at, clock_skew) \
+        SELECT COALESCE(t.system, ''), COALESCE(t.os_version, ''), COALESCE(t.hostname, ''), \
+            COALESCE(t.uptime, 0), t.uuid, t.created_at, t.clock_skew \
+        FROM {columns} \
+        ON CONFLICT (uuid) DO NOTHING"
+    )))
+    .execute(conn)?;
+
+    bind_columns!(sql_query(format!(
+        "UPDATE hosts SET \
+            system = COALESCE(t.system, hosts.system), \
+            os_version = COALESCE(t.os_version, hosts.os_version), \
+            hostname = COALESCE(t.hostname, hosts.hostname), \
+            uptime = COALESCE(t.uptime, hosts.uptime), \
+            clock_skew = COALESCE(t.clock_skew, hosts.clock_skew) \
+        FROM {columns} \
+        WHERE hosts.uuid = t.uuid"
+    )))
+    .execute(conn)?;
+
+    Ok(())
+}
+
+/// Update the current state of the units, recording a transition when
+/// the state (or the restarts count) changed since the previous report.
+/// Reports older than the current state are ignored.
+/// Everything is done in one statement: the reports are chained (in order,
+/// after the current state) so that a batch yields every transition.
+fn upsert_units(conn: &mut PgConnection, units: &[UnitUpsert]) -> Result<(), ApiError> {
+    if units.is_empty() {
+        return Ok(());
+    }
+
+    let host_uuids: Vec<_> = units.iter().map(|unit| &unit.host_uuid).collect();
+    let names: Vec<_> = units.iter().map(|unit| &unit.unit).collect();
+    let states: Vec<_> = units.iter().map(|unit| &unit.state).collect();
+    let restarts: Vec<_> = units.iter().map(|unit| unit.restarts).collect();
+    let created_ats: Vec<_> = units.iter().map(|unit| unit.created_at).collect();
+
+    // changed_at is only set on the current state (not on the reports), and
+    // run_start is when the state of the report was entered
+    sql_query(
+        "WITH reports AS ( \
+            SELECT DISTINCT ON (host_uuid, unit, created_at) * \
+            FROM unnest($1::text[], $2::text[], $3::text[], $4::int8[], $5::timestamp[]) \
+                AS t(host_uuid, unit, state, restarts, created_at) \
+            ORDER BY host_uuid, unit, created_at \
+        ), merged AS ( \
+            SELECT u.host_uuid, u.unit, u.state, u.restarts, u.created_at, u.changed_at FROM units u \
+            WHERE (u.host_uuid, u.unit) IN (SELECT host_uuid, unit FROM reports) \
+            UNION ALL \
+            SELECT r.host_uuid, r.unit, r.state, r.restarts, r.created_at, NULL FROM reports r \
+            LEFT JOIN units u ON u.host_uuid = r.host_uuid AND u.unit = r.unit \
+            WHERE u.created_at IS NULL OR u.created_at < r.created_at \
+        ), chained AS ( \
+            SELECT *, LAG(state) OVER w AS prev_state, LAG(restarts) OVER w AS prev_restarts, \
+                LAG(created_at) OVER w AS prev_created_at \
+            FROM merged WINDOW w AS (PARTITION BY host_uuid, unit ORDER BY created_at) \
+        ), runs AS ( \
+            SELECT *, MAX(CASE WHEN changed_at IS NOT NULL THEN changed_at \
+                WHEN prev_created_at IS NULL OR prev_state <> state THEN created_at END) \
+                OVER (PARTITION BY host_uuid, unit ORDER BY created_at) AS run_start \
+            FROM chained \
+        ), transition AS ( \
+            INSERT INTO unit_transitions (unit, from_state, to_state, restarts, host_uuid, created_at) \
+            SELECT unit, prev_state, state, restarts, host_uuid, created_at FROM runs \
+            WHERE changed_at IS NULL AND (prev_created_at IS NULL \
+                OR (prev_state, prev_restarts) IS DISTINCT FROM (state, restarts)) \
+        ) \
+        INSERT INTO units (host_uuid, unit, state, failed, restarts, changed_at, created_at) \
+        SELECT DISTINCT ON (host_uuid, unit) host_uuid, unit, state, (state = 'failed')::int, \
+            restarts, run_start, created_at \
+        FROM runs WHERE changed_at IS NULL \
+        ORDER BY host_uuid, unit, created_at DESC \
+        ON CONFLICT (host_uuid, unit) DO UPDATE SET \
+            state = EXCLUDED.state, \
+            failed = EXCLUDED.failed, \
+            restarts = EXCLUDED.restarts, \
+            changed_at = EXCLUDED.changed_at, \
+            created_at = EXCLUDED.created_at \
+        WHERE units.created_at < EXCLUDED.created_at",
+    )
+    .bind::<Array<Text>, _>(&host_uuids)
+    .bind::<Array<Text>, _>(&names)
+    .bind::<Array<Text>, _>(&states)
+    .bind::<Array<BigInt>, _>(&restarts)
+    .bind::<Array<Timestamp>, _>(&created_ats)
+    .execute(conn)?;
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use chrono::NaiveDateTime;
+use diesel::sql_types::{BigInt, Double, Integer, Text, Timestamp};
+use diesel::{sql_query, QueryableByName, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use sproot::apierrors::ApiError;
+use sproot::ConnType;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessSort {
+    #[default]
+    Cpu,
+    Memory,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct TopProcess {
+    #[diesel(sql_type = Integer)]
+    pub pid: i32,
+    #[diesel(sql_type = Text)]
+    pub name: String,
+    #[diesel(sql_type = Text)]
+    pub cmdline_hash: String,
+    #[diesel(sql_type = Double)]
+    pub cpu_avg: f64,
+    #[diesel(sql_type = Double)]
+    pub cpu_max: f64,
+    #[diesel(sql_type = BigInt)]
+    pub rss_avg: i64,
+    #[diesel(sql_type = BigInt)]
+    pub rss_max: i64,
+    /// Bytes read during the range
+    #[diesel(sql_type = BigInt)]
+    pub read_bytes: i64,
+    /// Bytes written during the range
+    #[diesel(sql_type = BigInt)]
+    pub write_bytes: i64,
+    /// Number of samples in which the process was seen
+    #[diesel(sql_type = BigInt)]
+    pub samples: i64,
+}
+
+impl TopProcess {
+    /// Get the `limit` processes of a host using the most cpu (or memory)
+    /// on average between min_date and max_date. A process is identified
+    /// by its pid, name and cmdline hash, so a reused pid is another process.
+    pub fn get_top(
+        conn: &mut ConnType,
+        uuid: &str,
+        min_date: NaiveDateTime,
+        max_date: NaiveDateTime,
+        sort: ProcessSort,
+        limit: i64,
+    ) -> Result<Vec<Self>, ApiError> {
+        let order = match sort {
+            ProcessSort::Cpu => "cpu_avg",
+            ProcessSort::Memory => "rss_avg",
+        };
+
+        Ok(sql_query(format!(
+            "SELECT pid, name, cmdline_hash, \
+            avg(cpu)::float8 as cpu_avg, max(cpu) as cpu_max, \
+            avg(rss)::int8 as rss_avg, max(rss) as rss_max, \
+            max(read_bytes) - min(read_bytes) as read_bytes, \
+            max(write_bytes) - min(write_bytes) as write_bytes, \
+            count(*) as samples \
+            FROM processes \
+            WHERE host_uuid = $1 AND created_at BETWEEN $2 AND $3 \
+            GROUP BY pid, name, cmdline_hash \
+            ORDER BY {order} DESC \
+            LIMIT $4",
+        ))
+        .bind::<Text, _>(uuid)
+        .bind::<Timestamp, _>(min_date)
+        .bind::<Timestamp, _>(max_date)
+        .bind::<BigInt, _>(limit)
+        .load(conn)?)
+    }
+}
@@ -0,0 +1,288 @@
+//! Per-second rates of the counters (cputimes, cpustats, ioblocks and
+//! ionets), computed per series between consecutive rows. No rate is
+//! returned for the interval ending on a row if:
+//! - a counter went down: it was reset (reboot, driver reload...), the
+//!   rows after it are compared to that new start
+//! - the interval is longer than GAP_FACTOR times the median interval of
+//!   the series: the host didn't report for a while, the rate would
+//!   flatten whatever happened during the gap
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+use super::{downsample, CpuStatsPoint, CpuTimesPoint, Downsample, IoBlockPoint, IoNetPoint};
+
+/// Intervals longer than this many times the median one are gaps
+const GAP_FACTOR: i64 = 3;
+
+pub trait Counters: Downsample {
+    /// Name and value of the counters of the row (None if not kept by the view)
+    fn counters(&self) -> Vec<(&'static str, Option<i64>)>;
+    /// Name and value of the other columns (gauges, device), returned as is
+    fn others(&self) -> Vec<(&'static str, Value)> {
+        Vec::new()
+    }
+}
+
+/// The per-second rates of the counters over the interval ending at created_at
+#[derive(Debug, Serialize)]
+pub struct Rate {
+    #[serde(flatten)]
+    pub columns: Map<String, Value>,
+    pub created_at: NaiveDateTime,
+    #[serde(skip)]
+    series: String,
+    #[serde(skip)]
+    values: Vec<f64>,
+}
+
+impl Downsample for Rate {
+    fn time(&self) -> NaiveDateTime {
+        self.created_at
+    }
+
+    fn values(&self) -> Vec<f64> {
+        self.values.to_owned()
+    }
+
+    fn series(&self) -> String {
+        self.series.to_owned()
+    }
+}
+
+/// The rows of a counters table, as is or as per-second rates
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum CounterRows<T> {
+    Raw(Vec<T>),
+    Rates(Vec<Rate>),
+}
+
+impl<T: Counters> CounterRows<T> {
+    /// The rows (or their rates if `rate`), downsampled to max_points
+    pub fn new(rows: Vec<T>, rate: bool, max_points: Option<usize>) -> Self {
+        if rate {
+            CounterRows::Rates(downsample(rates(rows), max_points))
+        } else {
+            CounterRows::Raw(downsample(rows, max_points))
+        }
+    }
+}
+
+/// Compute the rates of each series of `rows` (sorted by time)
+pub fn rates<T: Counters>(rows: Vec<T>) -> Vec<Rate> {
+    let mut series: BTreeMap<String, Vec<T>> = BTreeMap::new();
+    for row in rows {
+        series.entry(row.series()).or_default().push(row);
+    }
+
+    let interleaved = series.len() > 1;
+    let mut rates = series
+        .into_iter()
+        .flat_map(|(key, rows)| series_rates(key, &rows))
+        .collect::<Vec<_>>();
+    if interleaved {
+        rates.sort_by_key(|rate| rate.created_at);
+    }
+
+    rates
+}
+
+fn series_rates<T: Counters>(series: String, rows: &[T]) -> Vec<Rate> {
+    let mut intervals = rows
+        .windows(2)
+        .map(|pair| (pair[1].time() - pair[0].time()).num_milliseconds())
+        .collect::<Vec<_>>();
+    if intervals.is_empty() {
+        return Vec::new();
+    }
+    intervals.sort_unstable();
+    let max_interval = intervals[intervals.len() / 2].max(1) * GAP_FACTOR;
+
+    let mut rates = Vec::new();
+    for pair in rows.windows(2) {
+        let (prev, cur) = (&pair[0], &pair[1]);
+        let interval = (cur.time() - prev.time()).num_milliseconds();
+        if interval <= 0 || interval > max_interval {
+            continue;
+        }
+
+        let counters = prev.counters().into_iter().zip(cur.counters());
+        let mut columns = Map::new();
+        let mut values = Vec::new();
+        let mut reset = false;
+        for ((_, prev), (name, cur)) in counters {
+            let rate = match (prev, cur) {
+                (Some(prev), Some(cur)) if cur < prev => {
+                    reset = true;
+                    break;
+                }
+                (Some(prev), Some(cur)) => Some((cur - prev) as f64 * 1000.0 / interval as f64),
+                _ => None,
+            };
+            values.push(rate.unwrap_or_default());
+            columns.insert(name.to_owned(), rate.into());
+        }
+        if reset {
+            continue;
+        }
+
+        for (name, value) in cur.others() {
+            columns.insert(name.to_owned(), value);
+        }
+        rates.push(Rate {
+            columns,
+            created_at: cur.time(),
+            series: series.to_owned(),
+            values,
+        });
+    }
+
+    rates
+}
+
+macro_rules! counters {
+    ($model:ident, [$($counter:ident),*] $(, others: [$($other:ident),*])?) => {
+        impl Counters for $model {
+            fn counters(&self) -> Vec<(&'static str, Option<i64>)> {
+                vec![$((stringify!($counter), self.$counter.into())),*]
+            }
+
+            $(
+                fn others(&self) -> Vec<(&'static str, Value)> {
+                    vec![$((stringify!($other), self.$other.to_owned().into())),*]
+                }
+            )?
+        }
+    };
+}
+
+counters!(
+    CpuTimesPoint,
+    [cuser, nice, system, idle, iowait, irq, softirq, steal, guest, guest_nice]
+);
+counters!(
+    CpuStatsPoint,
+    [interrupts, ctx_switches, soft_interrupts, processes],
+    others: [procs_running, procs_blocked]
+);
+counters!(
+    IoBlockPoint,
+    [read_count, read_bytes, write_count, write_bytes, busy_time],
+    others: [device_name]
+);
+counters!(
+    IoNetPoint,
+    [rx_bytes, rx_packets, rx_errs, rx_drop, tx_bytes, tx_packets, tx_errs, tx_drop],
+    others: [interface]
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Row {
+        at: i64,
+        value: Option<i64>,
+    }
+
+    impl Downsample for Row {
+        fn time(&self) -> NaiveDateTime {
+            chrono::DateTime::from_timestamp(self.at, 0)
+                .unwrap()
+                .naive_utc()
+        }
+
+        fn values(&self) -> Vec<f64> {
+            vec![self.value.unwrap_or_default() as f64]
+        }
+    }
+
+    impl Counters for Row {
+        fn counters(&self) -> Vec<(&'static str, Option<i64>)> {
+            vec![("value", self.value)]
+        }
+    }
+
+    fn rows(points: &[(i64, i64)]) -> Vec<Row> {
+        points
+            .iter()
+            .map(|(at, value)| Row {
+                at: *at,
+                value: Some(*value),
+            })
+            .collect()
+    }
+
+    /// (timestamp, rate) of each Rate
+    fn summary(rates: &[Rate]) -> Vec<(i64, Value)> {
+        rates
+            .iter()
+            .map(|rate| {
+                (
+                    rate.created_at.and_utc().timestamp(),
+                    rate.columns["value"].clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rates_per_second() {
+        let rates = series_rates(String::new(), &rows(&[(0, 0), (10, 100), (20, 300)]));
+        assert_eq!(
+            summary(&rates),
+            vec![(10, Value::from(10.0)), (20, Value::from(20.0))]
+        );
+    }
+
+    #[test]
+    fn rates_skip_counter_reset() {
+        let rates = series_rates(
+            String::new(),
+            &rows(&[(0, 0), (10, 100), (20, 50), (30, 150)]),
+        );
+        // No rate ending at 20, the one ending at 30 starts over from 50
+        assert_eq!(
+            summary(&rates),
+            vec![(10, Value::from(10.0)), (30, Value::from(10.0))]
+        );
+    }
+
+    #[test]
+    fn rates_skip_gaps() {
+        // Median interval of 10s, 70s is over GAP_FACTOR times that
+        let rates = series_rates(
+            String::new(),
+            &rows(&[(0, 0), (10, 10), (20, 20), (30, 30), (100, 100), (110, 110)]),
+        );
+        assert_eq!(
+            summary(&rates),
+            vec![
+                (10, Value::from(1.0)),
+                (20, Value::from(1.0)),
+                (30, Value::from(1.0)),
+                (110, Value::from(1.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rates_of_missing_counters() {
+        let rows = vec![
+            Row { at: 0, value: None },
+            Row {
+                at: 10,
+                value: Some(10),
+            },
+        ];
+        let rates = series_rates(String::new(), &rows);
+        assert_eq!(summary(&rates), vec![(10, Value::Null)]);
+    }
+
+    #[test]
+    fn rates_of_a_single_row() {
+        assert!(series_rates(String::new(), &rows(&[(0, 0)])).is_empty());
+    }
+}
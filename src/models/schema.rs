@@ -0,0 +1,278 @@
+// Diesel definitions of the tables the server writes to directly.
+// They must be kept in sync with the migrations.
+
+diesel::table! {
+    hosts (uuid) {
+        system -> Varchar,
+        os_version -> Varchar,
+        hostname -> Varchar,
+        uptime -> Int8,
+        uuid -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        sync_interval -> Int8,
+        clock_skew -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    cputimes (id) {
+        id -> Int8,
+        cuser -> Int8,
+        nice -> Int8,
+        system -> Int8,
+        idle -> Int8,
+        iowait -> Int8,
+        irq -> Int8,
+        softirq -> Int8,
+        steal -> Int8,
+        guest -> Int8,
+        guest_nice -> Int8,
+        host_uuid -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    cpustats (id) {
+        id -> Int8,
+        interrupts -> Int8,
+        ctx_switches -> Int8,
+        soft_interrupts -> Int8,
+        processes -> Int8,
+        procs_running -> Int8,
+        procs_blocked -> Int8,
+        host_uuid -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    disks (id) {
+        id -> Int8,
+        disk_name -> Varchar,
+        mount_point -> Varchar,
+        total_space -> Int8,
+        avail_space -> Int8,
+        host_uuid -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    ioblocks (id) {
+        id -> Int8,
+        device_name -> Varchar,
+        read_count -> Int8,
+        read_bytes -> Int8,
+        write_count -> Int8,
+        write_bytes -> Int8,
+        busy_time -> Int8,
+        host_uuid -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    ionets (id) {
+        id -> Int8,
+        interface -> Varchar,
+        rx_bytes -> Int8,
+        rx_packets -> Int8,
+        rx_errs -> Int8,
+        rx_drop -> Int8,
+        tx_bytes -> Int8,
+        tx_packets -> Int8,
+        tx_errs -> Int8,
+        tx_drop -> Int8,
+        host_uuid -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    loadavg (id) {
+        id -> Int8,
+        one -> Float8,
+        five -> Float8,
+        fifteen -> Float8,
+        host_uuid -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    memory (id) {
+        id -> Int8,
+        total -> Int8,
+        free -> Int8,
+        used -> Int8,
+        shared -> Int8,
+        buffers -> Int8,
+        cached -> Int8,
+        host_uuid -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    swap (id) {
+        id -> Int8,
+        total -> Int8,
+        free -> Int8,
+        used -> Int8,
+        host_uuid -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    custom_metrics (id) {
+        id -> Int8,
+        name -> Varchar,
+        labels -> Jsonb,
+        value -> Float8,
+        host_uuid -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    processes (id) {
+        id -> Int8,
+        pid -> Int4,
+        name -> Varchar,
+        cmdline_hash -> Varchar,
+        cpu -> Float8,
+        rss -> Int8,
+        read_bytes -> Int8,
+        write_bytes -> Int8,
+        host_uuid -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    containers (id) {
+        id -> Int8,
+        container_id -> Varchar,
+        name -> Varchar,
+        cpu_usage -> Int8,
+        memory_usage -> Int8,
+        memory_limit -> Int8,
+        read_bytes -> Int8,
+        write_bytes -> Int8,
+        rx_bytes -> Int8,
+        tx_bytes -> Int8,
+        host_uuid -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    sensors (id) {
+        id -> Int8,
+        label -> Varchar,
+        kind -> Varchar,
+        value -> Float8,
+        crit -> Nullable<Float8>,
+        host_uuid -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    smart (id) {
+        id -> Int8,
+        disk_name -> Varchar,
+        reallocated_sectors -> Int8,
+        pending_sectors -> Int8,
+        wear_level -> Nullable<Int8>,
+        power_on_hours -> Int8,
+        failing -> Int4,
+        host_uuid -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    tcpstates (id) {
+        id -> Int8,
+        established -> Int8,
+        syn_sent -> Int8,
+        syn_recv -> Int8,
+        fin_wait1 -> Int8,
+        fin_wait2 -> Int8,
+        time_wait -> Int8,
+        close -> Int8,
+        close_wait -> Int8,
+        last_ack -> Int8,
+        listen -> Int8,
+        closing -> Int8,
+        host_uuid -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    kernelstats (id) {
+        id -> Int8,
+        open_fds -> Int8,
+        max_fds -> Int8,
+        conntrack_count -> Int8,
+        conntrack_max -> Int8,
+        entropy -> Int8,
+        host_uuid -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    units (host_uuid, unit) {
+        host_uuid -> Varchar,
+        unit -> Varchar,
+        state -> Varchar,
+        failed -> Int4,
+        restarts -> Int8,
+        changed_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    unit_transitions (id) {
+        id -> Int8,
+        unit -> Varchar,
+        from_state -> Nullable<Varchar>,
+        to_state -> Varchar,
+        restarts -> Int8,
+        host_uuid -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    enrollments (id) {
+        id -> Int8,
+        host_uuid -> Varchar,
+        key_id -> Int8,
+        customer_id -> Uuid,
+        berta -> Varchar,
+        remote_addr -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    dead_letters (id) {
+        id -> Int8,
+        host_uuid -> Nullable<Varchar>,
+        claimed_uuid -> Nullable<Varchar>,
+        route -> Varchar,
+        reason -> Text,
+        headers -> Jsonb,
+        body -> Bytea,
+        replayed_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
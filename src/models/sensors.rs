@@ -0,0 +1,89 @@
+use chrono::NaiveDateTime;
+use diesel::sql_types::{BigInt, Double, Integer, Nullable, Text, Timestamp};
+use diesel::{sql_query, QueryableByName, RunQueryDsl};
+use serde::Serialize;
+use sproot::apierrors::ApiError;
+use sproot::ConnType;
+
+use super::{dated_source, time_column, Granularity};
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct Sensor {
+    #[diesel(sql_type = Text)]
+    pub label: String,
+    #[diesel(sql_type = Text)]
+    pub kind: String,
+    #[diesel(sql_type = Double)]
+    pub value: f64,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub crit: Option<f64>,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct Smart {
+    #[diesel(sql_type = Text)]
+    pub disk_name: String,
+    #[diesel(sql_type = BigInt)]
+    pub reallocated_sectors: i64,
+    #[diesel(sql_type = BigInt)]
+    pub pending_sectors: i64,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub wear_level: Option<i64>,
+    #[diesel(sql_type = BigInt)]
+    pub power_on_hours: i64,
+    #[diesel(sql_type = Integer)]
+    pub failing: i32,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+impl Sensor {
+    /// Get the sensors of a host between min_date and max_date
+    pub fn get_dated(
+        conn: &mut ConnType,
+        uuid: &str,
+        granularity: Granularity,
+        min_date: NaiveDateTime,
+        max_date: NaiveDateTime,
+    ) -> Result<Vec<Self>, ApiError> {
+        let source = dated_source("sensors", granularity, min_date, max_date);
+        let time = time_column("sensors", &source);
+
+        Ok(sql_query(format!(
+            "SELECT label, kind, value, crit, {time} as created_at FROM {source} \
+            WHERE host_uuid = $1 AND {time} BETWEEN $2 AND $3 \
+            ORDER BY {time}",
+        ))
+        .bind::<Text, _>(uuid)
+        .bind::<Timestamp, _>(min_date)
+        .bind::<Timestamp, _>(max_date)
+        .load(conn)?)
+    }
+}
+
+impl Smart {
+    /// Get the SMART attributes of the disks of a host between min_date and max_date
+    pub fn get_dated(
+        conn: &mut ConnType,
+        uuid: &str,
+        granularity: Granularity,
+        min_date: NaiveDateTime,
+        max_date: NaiveDateTime,
+    ) -> Result<Vec<Self>, ApiError> {
+        let source = dated_source("smart", granularity, min_date, max_date);
+        let time = time_column("smart", &source);
+
+        Ok(sql_query(format!(
+            "SELECT disk_name, reallocated_sectors, pending_sectors, wear_level, \
+            power_on_hours, failing, {time} as created_at FROM {source} \
+            WHERE host_uuid = $1 AND {time} BETWEEN $2 AND $3 \
+            ORDER BY {time}",
+        ))
+        .bind::<Text, _>(uuid)
+        .bind::<Timestamp, _>(min_date)
+        .bind::<Timestamp, _>(max_date)
+        .load(conn)?)
+    }
+}
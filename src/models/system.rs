@@ -0,0 +1,263 @@
+use chrono::NaiveDateTime;
+use diesel::pg::Pg;
+use diesel::sql_types::{BigInt, Double, Nullable, Text, Timestamp};
+use diesel::{sql_query, QueryableByName, RunQueryDsl};
+use serde::Serialize;
+use sproot::apierrors::ApiError;
+use sproot::ConnType;
+
+use super::{dated_source, time_column, Granularity};
+
+// The system metrics read at a chosen granularity (the sproot models only
+// read the raw tables). The views don't keep every column of the raw
+// tables, those are null when read from a view.
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct CpuTimesPoint {
+    #[diesel(sql_type = BigInt)]
+    pub cuser: i64,
+    #[diesel(sql_type = BigInt)]
+    pub nice: i64,
+    #[diesel(sql_type = BigInt)]
+    pub system: i64,
+    #[diesel(sql_type = BigInt)]
+    pub idle: i64,
+    #[diesel(sql_type = BigInt)]
+    pub iowait: i64,
+    #[diesel(sql_type = BigInt)]
+    pub irq: i64,
+    #[diesel(sql_type = BigInt)]
+    pub softirq: i64,
+    #[diesel(sql_type = BigInt)]
+    pub steal: i64,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub guest: Option<i64>,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub guest_nice: Option<i64>,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct CpuStatsPoint {
+    #[diesel(sql_type = BigInt)]
+    pub interrupts: i64,
+    #[diesel(sql_type = BigInt)]
+    pub ctx_switches: i64,
+    #[diesel(sql_type = BigInt)]
+    pub soft_interrupts: i64,
+    #[diesel(sql_type = BigInt)]
+    pub processes: i64,
+    #[diesel(sql_type = BigInt)]
+    pub procs_running: i64,
+    #[diesel(sql_type = BigInt)]
+    pub procs_blocked: i64,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct LoadAvgPoint {
+    #[diesel(sql_type = Double)]
+    pub one: f64,
+    #[diesel(sql_type = Double)]
+    pub five: f64,
+    #[diesel(sql_type = Double)]
+    pub fifteen: f64,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct MemoryPoint {
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub total: Option<i64>,
+    #[diesel(sql_type = BigInt)]
+    pub free: i64,
+    #[diesel(sql_type = BigInt)]
+    pub used: i64,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub shared: Option<i64>,
+    #[diesel(sql_type = BigInt)]
+    pub buffers: i64,
+    #[diesel(sql_type = BigInt)]
+    pub cached: i64,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct SwapPoint {
+    #[diesel(sql_type = BigInt)]
+    pub total: i64,
+    #[diesel(sql_type = BigInt)]
+    pub free: i64,
+    #[diesel(sql_type = BigInt)]
+    pub used: i64,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct DiskPoint {
+    #[diesel(sql_type = Text)]
+    pub disk_name: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub mount_point: Option<String>,
+    #[diesel(sql_type = BigInt)]
+    pub total_space: i64,
+    #[diesel(sql_type = BigInt)]
+    pub avail_space: i64,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct IoBlockPoint {
+    #[diesel(sql_type = Text)]
+    pub device_name: String,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub read_count: Option<i64>,
+    #[diesel(sql_type = BigInt)]
+    pub read_bytes: i64,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub write_count: Option<i64>,
+    #[diesel(sql_type = BigInt)]
+    pub write_bytes: i64,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub busy_time: Option<i64>,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct IoNetPoint {
+    #[diesel(sql_type = Text)]
+    pub interface: String,
+    #[diesel(sql_type = BigInt)]
+    pub rx_bytes: i64,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub rx_packets: Option<i64>,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub rx_errs: Option<i64>,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub rx_drop: Option<i64>,
+    #[diesel(sql_type = BigInt)]
+    pub tx_bytes: i64,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub tx_packets: Option<i64>,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub tx_errs: Option<i64>,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    pub tx_drop: Option<i64>,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+/// Read `columns` (plus `raw_columns`, of the given SQL types, if the source
+/// is the raw table) of the rows of `table` of a host between min_date and max_date
+#[allow(clippy::too_many_arguments)]
+fn get_dated<T: QueryableByName<Pg> + 'static>(
+    conn: &mut ConnType,
+    table: &str,
+    columns: &str,
+    raw_columns: &[(&str, &str)],
+    uuid: &str,
+    granularity: Granularity,
+    min_date: NaiveDateTime,
+    max_date: NaiveDateTime,
+) -> Result<Vec<T>, ApiError> {
+    let source = dated_source(table, granularity, min_date, max_date);
+    let time = time_column(table, &source);
+
+    let mut columns = columns.to_owned();
+    for (name, sql_type) in raw_columns {
+        if source == table {
+            columns.push_str(&format!(", {}", name));
+        } else {
+            columns.push_str(&format!(", NULL::{} as {}", sql_type, name));
+        }
+    }
+
+    Ok(sql_query(format!(
+        "SELECT {columns}, {time} as created_at FROM {source} \
+        WHERE host_uuid = $1 AND {time} BETWEEN $2 AND $3 \
+        ORDER BY {time}",
+    ))
+    .bind::<Text, _>(uuid)
+    .bind::<Timestamp, _>(min_date)
+    .bind::<Timestamp, _>(max_date)
+    .load(conn)?)
+}
+
+macro_rules! dated {
+    ($model:ident, $table:literal, $columns:literal, [$($raw:literal: $type:literal),*]) => {
+        impl $model {
+            /// Get the metrics of a host between min_date and max_date, at the given granularity
+            pub fn get_dated(
+                conn: &mut ConnType,
+                uuid: &str,
+                granularity: Granularity,
+                min_date: NaiveDateTime,
+                max_date: NaiveDateTime,
+            ) -> Result<Vec<Self>, ApiError> {
+                get_dated(
+                    conn,
+                    $table,
+                    $columns,
+                    &[$(($raw, $type)),*],
+                    uuid,
+                    granularity,
+                    min_date,
+                    max_date,
+                )
+            }
+        }
+    };
+}
+
+dated!(
+    CpuTimesPoint,
+    "cputimes",
+    "cuser, nice, system, idle, iowait, irq, softirq, steal",
+    ["guest": "int8", "guest_nice": "int8"]
+);
+dated!(
+    CpuStatsPoint,
+    "cpustats",
+    "interrupts, ctx_switches, soft_interrupts, processes, procs_running, procs_blocked",
+    []
+);
+dated!(LoadAvgPoint, "loadavg", "one, five, fifteen", []);
+dated!(
+    MemoryPoint,
+    "memory",
+    "free, used, buffers, cached",
+    ["total": "int8", "shared": "int8"]
+);
+dated!(SwapPoint, "swap", "total, free, used", []);
+dated!(
+    DiskPoint,
+    "disks",
+    "disk_name, total_space, avail_space",
+    ["mount_point": "text"]
+);
+dated!(
+    IoBlockPoint,
+    "ioblocks",
+    "device_name, read_bytes, write_bytes",
+    ["read_count": "int8", "write_count": "int8", "busy_time": "int8"]
+);
+dated!(
+    IoNetPoint,
+    "ionets",
+    "interface, rx_bytes, tx_bytes",
+    [
+        "rx_packets": "int8",
+        "rx_errs": "int8",
+        "rx_drop": "int8",
+        "tx_packets": "int8",
+        "tx_errs": "int8",
+        "tx_drop": "int8"
+    ]
+);
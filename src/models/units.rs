@@ -0,0 +1,75 @@
+use chrono::NaiveDateTime;
+use diesel::sql_types::{BigInt, Integer, Nullable, Text, Timestamp};
+use diesel::{sql_query, QueryableByName, RunQueryDsl};
+use serde::Serialize;
+use sproot::apierrors::ApiError;
+use sproot::ConnType;
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct UnitState {
+    #[diesel(sql_type = Text)]
+    pub unit: String,
+    #[diesel(sql_type = Text)]
+    pub state: String,
+    #[diesel(sql_type = Integer)]
+    pub failed: i32,
+    #[diesel(sql_type = BigInt)]
+    pub restarts: i64,
+    /// Time of the last change of state
+    #[diesel(sql_type = Timestamp)]
+    pub changed_at: NaiveDateTime,
+    /// Time of the last report
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, QueryableByName)]
+pub struct UnitTransition {
+    #[diesel(sql_type = Text)]
+    pub unit: String,
+    /// None for the first report of the unit
+    #[diesel(sql_type = Nullable<Text>)]
+    pub from_state: Option<String>,
+    #[diesel(sql_type = Text)]
+    pub to_state: String,
+    #[diesel(sql_type = BigInt)]
+    pub restarts: i64,
+    #[diesel(sql_type = Timestamp)]
+    pub created_at: NaiveDateTime,
+}
+
+impl UnitState {
+    /// Get the current state of the units of a host
+    pub fn get_specific(conn: &mut ConnType, uuid: &str) -> Result<Vec<Self>, ApiError> {
+        Ok(sql_query(
+            "SELECT unit, state, failed, restarts, changed_at, created_at FROM units \
+            WHERE host_uuid = $1 ORDER BY unit",
+        )
+        .bind::<Text, _>(uuid)
+        .load(conn)?)
+    }
+}
+
+impl UnitTransition {
+    /// Get the transitions of the units (optionally only `unit`)
+    /// of a host between min_date and max_date
+    pub fn get_dated(
+        conn: &mut ConnType,
+        uuid: &str,
+        unit: Option<&str>,
+        min_date: NaiveDateTime,
+        max_date: NaiveDateTime,
+    ) -> Result<Vec<Self>, ApiError> {
+        Ok(sql_query(
+            "SELECT unit, from_state, to_state, restarts, created_at FROM unit_transitions \
+            WHERE host_uuid = $1 AND ($2 IS NULL OR unit = $2) \
+            AND created_at BETWEEN $3 AND $4 \
+            ORDER BY created_at",
+        )
+        .bind::<Text, _>(uuid)
+        .bind::<Nullable<Text>, _>(unit)
+        .bind::<Timestamp, _>(min_date)
+        .bind::<Timestamp, _>(max_date)
+        .load(conn)?)
+    }
+}
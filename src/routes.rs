@@ -2,27 +2,87 @@ use actix_web::{guard, web};
 use {
     crate::auth::{
         alert_host_owned::AlertHostOwned, alert_owned::AlertOwned, check_sessions::CheckSessions,
-        sptk_validator::SptkValidator,
+        ingest_limiter::IngestLimiter, sptk_validator::SptkValidator,
     },
+    actix_session::Session,
     sproot::get_session_middleware,
 };
 
 use crate::{
     api::{
-        alerts, cpustats, cputimes, disks, hosts, incidents, ioblock, ionet, loadavg, memory, swap,
+        alerts, containers, cpustats, cputimes, custom, dead_letters, disks, get_user_session,
+        hosts, hosts_ws, incidents, influx, ioblock, ionet, kernelstats, loadavg, memory, otlp,
+        processes, prom, sensors, smart, swap, tcpstates, units,
     },
+    utils::{payload, stats::STATS},
     CONFIG,
 };
 
 pub fn routes(cfg: &mut web::ServiceConfig) {
     cfg.route("/ping", web::get().to(|| async { "zpour" }))
         .route("/ping", web::head().to(|| async { "zpour" }))
+        .service(
+            web::resource("/metrics")
+                // The counters are only for the logged in users
+                .wrap(get_session_middleware(
+                    CONFIG.cookie_secret.as_bytes(),
+                    "SP-CKS".to_string(),
+                    CONFIG.cookie_domain.to_owned(),
+                ))
+                .route(web::get().to(|session: Session| async move {
+                    get_user_session(&session).map(|_| STATS.render())
+                })),
+        )
         .service(
             web::resource("/api/hosts")
                 .guard(guard::Post())
+                // SptkValidator runs first, unauthorized requests don't take tokens
+                .wrap(IngestLimiter)
                 .wrap(SptkValidator)
+                .app_data(web::PayloadConfig::new(payload::MAX_PAYLOAD_SIZE))
                 .route(web::post().to(hosts::host_ingest)),
         )
+        .service(
+            web::resource("/api/hosts/backfill")
+                .guard(guard::Post())
+                .wrap(SptkValidator)
+                .app_data(web::PayloadConfig::new(payload::MAX_PAYLOAD_SIZE))
+                .route(web::post().to(hosts::host_backfill)),
+        )
+        .service(
+            web::resource("/api/hosts/ws")
+                .guard(guard::Get())
+                .wrap(SptkValidator)
+                .route(web::get().to(hosts_ws::host_stream)),
+        )
+        .service(
+            web::resource("/api/custom")
+                .guard(guard::Post())
+                .wrap(SptkValidator)
+                .app_data(web::PayloadConfig::new(payload::MAX_PAYLOAD_SIZE))
+                .route(web::post().to(custom::custom_ingest)),
+        )
+        .service(
+            web::resource("/api/otlp/v1/metrics")
+                .guard(guard::Post())
+                .wrap(SptkValidator)
+                .app_data(web::PayloadConfig::new(payload::MAX_RECEIVER_PAYLOAD_SIZE))
+                .route(web::post().to(otlp::otlp_metrics)),
+        )
+        .service(
+            web::resource("/api/prom/write")
+                .guard(guard::Post())
+                .wrap(SptkValidator)
+                .app_data(web::PayloadConfig::new(payload::MAX_RECEIVER_PAYLOAD_SIZE))
+                .route(web::post().to(prom::prom_write)),
+        )
+        .service(
+            web::resource("/api/influx/write")
+                .guard(guard::Post())
+                .wrap(SptkValidator)
+                .app_data(web::PayloadConfig::new(payload::MAX_RECEIVER_PAYLOAD_SIZE))
+                .route(web::post().to(influx::influx_write)),
+        )
         .service(
             web::resource("/api/hosts")
                 .wrap(get_session_middleware(
@@ -106,6 +166,29 @@ pub fn routes(cfg: &mut web::ServiceConfig) {
                 .route("/ionets", web::get().to(ionet::ionets))
                 .route("/memory", web::get().to(memory::memory))
                 .route("/swap", web::get().to(swap::swap))
+                .route("/sensors", web::get().to(sensors::sensors))
+                .route("/smart", web::get().to(smart::smart))
+                .route("/tcpstates", web::get().to(tcpstates::tcpstates))
+                .route("/kernelstats", web::get().to(kernelstats::kernelstats))
+                .route("/units", web::get().to(units::units))
+                .route("/units/timeline", web::get().to(units::units_timeline))
+                .route("/processes", web::get().to(processes::processes))
+                .route("/containers", web::get().to(containers::containers))
+                .route(
+                    "/containers/list",
+                    web::get().to(containers::containers_list),
+                )
+                .route("/custom", web::get().to(custom::custom))
+                .route("/custom/names", web::get().to(custom::custom_names))
+                .route("/dead_letters", web::get().to(dead_letters::dead_letters))
+                .route(
+                    "/dead_letters/entry",
+                    web::get().to(dead_letters::dead_letters_entry),
+                )
+                .route(
+                    "/dead_letters/replay",
+                    web::post().to(dead_letters::dead_letters_replay),
+                )
                 .route(
                     "/incidents/count",
                     web::get().to(incidents::incidents_count),
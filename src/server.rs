@@ -6,6 +6,7 @@ use sproot::models::MetricsPool;
 use sproot::Pool;
 
 use super::routes;
+use super::utils::tls;
 use super::CONFIG;
 
 /// Construct and run the actix server instance
@@ -23,6 +24,7 @@ pub async fn server(pool: Pool) -> std::io::Result<()> {
 
         app.configure(routes::routes)
     })
+    .on_connect(tls::on_connect)
     .workers(CONFIG.workers);
 
     // Bind the server (https or http)
@@ -34,10 +36,12 @@ pub async fn server(pool: Pool) -> std::io::Result<()> {
         info!("Server started as HTTP on {}", &CONFIG.binding);
         serve.bind(&CONFIG.binding)?.run()
     } else {
-        let tls_config = unwrapf!(sproot::get_ssl_builder(
-            unwrapf!(field_isset!(CONFIG.key_priv.as_ref(), "key_priv")),
-            unwrapf!(field_isset!(CONFIG.key_cert.as_ref(), "key_cert")),
-        ));
+        let key_priv = unwrapf!(field_isset!(CONFIG.key_priv.as_ref(), "key_priv"));
+        let key_cert = unwrapf!(field_isset!(CONFIG.key_cert.as_ref(), "key_cert"));
+        let tls_config = match &CONFIG.client_ca {
+            Some(client_ca) => unwrapf!(tls::mtls_config(key_priv, key_cert, client_ca)),
+            None => unwrapf!(sproot::get_ssl_builder(key_priv, key_cert)),
+        };
 
         info!("Server started as HTTPS on {}", &CONFIG.binding);
         serve.bind_rustls_0_23(&CONFIG.binding, tls_config)?.run()
@@ -2,6 +2,7 @@ use clap::Parser;
 use config::ConfigError;
 use serde::Deserialize;
 
+use crate::ingest::SkewPolicy;
 use crate::Args;
 
 #[derive(Debug, Deserialize, Clone)]
@@ -27,10 +28,37 @@ pub struct Config {
     pub https: bool,
     pub key_priv: Option<String>,
     pub key_cert: Option<String>,
+    pub client_ca: Option<String>,
 
     pub berta_name: String,
     pub cookie_secret: String,
     pub cookie_domain: Option<String>,
+    #[serde(default = "default_signature_max_age")]
+    pub signature_max_age: i64,
+    #[serde(default)]
+    pub auto_enroll: bool,
+
+    // INGESTION SETTINGS
+    #[serde(default = "default_queue_capacity")]
+    pub ingest_queue_capacity: usize,
+    #[serde(default = "default_flush_rows")]
+    pub ingest_flush_rows: usize,
+    #[serde(default = "default_flush_interval")]
+    pub ingest_flush_interval: u64,
+    #[serde(default = "default_ingest_burst")]
+    pub ingest_burst: u32,
+    #[serde(default)]
+    pub clock_skew_policy: SkewPolicy,
+    #[serde(default = "default_skew_tolerance")]
+    pub clock_skew_tolerance: i64,
+    #[serde(default = "default_dead_letters_max")]
+    pub dead_letters_max: i64,
+
+    // LISTENERS SETTINGS
+    pub statsd_binding: Option<String>,
+    #[serde(default = "default_statsd_flush")]
+    pub statsd_flush_interval: u64,
+    pub graphite_binding: Option<String>,
 }
 
 impl Config {
@@ -54,6 +82,14 @@ impl Config {
                 );
                 std::process::exit(1);
             }
+            if !config.https && config.client_ca.is_some() {
+                error!("error: config: 'client_ca' is defined but 'https' is false");
+                std::process::exit(1);
+            }
+            if config.ingest_flush_interval == 0 {
+                error!("error: config: 'ingest_flush_interval' must be at least 1 second");
+                std::process::exit(1);
+            }
         }
 
         config
@@ -68,6 +104,38 @@ fn default_maxconn() -> u32 {
     10
 }
 
+fn default_signature_max_age() -> i64 {
+    300
+}
+
+fn default_queue_capacity() -> usize {
+    200_000
+}
+
+fn default_flush_rows() -> usize {
+    20_000
+}
+
+fn default_flush_interval() -> u64 {
+    2
+}
+
+fn default_ingest_burst() -> u32 {
+    5
+}
+
+fn default_skew_tolerance() -> i64 {
+    30
+}
+
+fn default_dead_letters_max() -> i64 {
+    1000
+}
+
+fn default_statsd_flush() -> u64 {
+    10
+}
+
 fn default_workers() -> usize {
     match sys_metrics::cpu::get_logical_count() {
         Ok(count) => count as usize,
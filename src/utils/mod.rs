@@ -1,2 +1,5 @@
 pub mod config;
 pub mod database;
+pub mod payload;
+pub mod stats;
+pub mod tls;
@@ -0,0 +1,120 @@
+//! Decoding of the bodies sent by the agents to POST /api/hosts.
+//!
+//! The encoding is picked from the Content-Type header (JSON if absent).
+//! Compressed bodies (Content-Encoding: gzip or zstd) are decompressed
+//! by actix before reaching the handler, so the limits below apply to
+//! the decompressed body. Bodies over the JSON limit are rejected by actix
+//! with a 413, binary bodies over their own limit with a 400.
+//!
+//! | Content-Type          | Format      | Max decompressed size |
+//! |-----------------------|-------------|-----------------------|
+//! | application/json      | JSON        | 2 MiB                 |
+//! | application/msgpack   | MessagePack | 1 MiB                 |
+//! | application/cbor      | CBOR        | 1 MiB                 |
+//!
+//! The samples of POST /api/hosts extend the HttpHost with flattened fields,
+//! MessagePack samples must thus be encoded as maps (not as arrays).
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::HttpRequest;
+use serde::de::DeserializeOwned;
+use sproot::apierrors::ApiError;
+
+/// Largest body accepted, whatever the format (to be used with PayloadConfig)
+pub const MAX_PAYLOAD_SIZE: usize = JSON_MAX_SIZE;
+
+/// Largest body accepted by the receivers (prom, otlp and influx)
+pub const MAX_RECEIVER_PAYLOAD_SIZE: usize = 4 * 1024 * 1024;
+
+/// Largest body of the routes behind the SptkValidator, which reads the
+/// signed bodies whole (the limit of each route still applies after it)
+pub const MAX_SIGNED_PAYLOAD_SIZE: usize = if MAX_RECEIVER_PAYLOAD_SIZE > MAX_PAYLOAD_SIZE {
+    MAX_RECEIVER_PAYLOAD_SIZE
+} else {
+    MAX_PAYLOAD_SIZE
+};
+
+const JSON_MAX_SIZE: usize = 2 * 1024 * 1024;
+const MSGPACK_MAX_SIZE: usize = 1024 * 1024;
+const CBOR_MAX_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    MsgPack,
+    Cbor,
+}
+
+impl Format {
+    /// Get the Format of the body from the Content-Type of the request
+    pub fn from_request(req: &HttpRequest) -> Result<Self, ApiError> {
+        Self::from_content_type(
+            req.headers()
+                .get(CONTENT_TYPE)
+                .map(|val| val.to_str().unwrap_or_default()),
+        )
+    }
+
+    /// Get the Format of the body from a Content-Type (JSON if absent)
+    pub fn from_content_type(content_type: Option<&str>) -> Result<Self, ApiError> {
+        let content_type = match content_type {
+            Some(val) => val,
+            None => return Ok(Format::Json),
+        };
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+
+        match mime.as_str() {
+            "" | "application/json" => Ok(Format::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Ok(Format::MsgPack)
+            }
+            "application/cbor" => Ok(Format::Cbor),
+            other => Err(ApiError::InvalidRequestError(Some(format!(
+                "unsupported content-type '{}'",
+                other
+            )))),
+        }
+    }
+
+    pub fn max_size(&self) -> usize {
+        match self {
+            Format::Json => JSON_MAX_SIZE,
+            Format::MsgPack => MSGPACK_MAX_SIZE,
+            Format::Cbor => CBOR_MAX_SIZE,
+        }
+    }
+}
+
+/// Decode the body of the request according to its Content-Type
+pub fn decode<T: DeserializeOwned>(req: &HttpRequest, body: &[u8]) -> Result<T, ApiError> {
+    decode_as(Format::from_request(req)?, body)
+}
+
+/// Decode the body, expected to be encoded in `format`
+pub fn decode_as<T: DeserializeOwned>(format: Format, body: &[u8]) -> Result<T, ApiError> {
+    if body.len() > format.max_size() {
+        return Err(ApiError::InvalidRequestError(Some(format!(
+            "payload of {} bytes is over the {:?} limit of {} bytes",
+            body.len(),
+            format,
+            format.max_size()
+        ))));
+    }
+
+    let res = match format {
+        Format::Json => serde_json::from_slice(body).map_err(|err| err.to_string()),
+        Format::MsgPack => rmp_serde::from_slice(body).map_err(|err| err.to_string()),
+        Format::Cbor => ciborium::from_reader(body).map_err(|err| err.to_string()),
+    };
+
+    res.map_err(|err| {
+        ApiError::InvalidRequestError(Some(format!(
+            "cannot decode the {:?} payload: {}",
+            format, err
+        )))
+    })
+}
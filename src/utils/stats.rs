@@ -0,0 +1,57 @@
+//! Counters about the server itself, exposed on GET /metrics using
+//! the Prometheus text format (for the logged in users only).
+use once_cell::sync::Lazy;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::ingest::PIPELINE;
+
+pub static STATS: Lazy<Stats> = Lazy::new(Stats::default);
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    rate_limited: AtomicU64,
+    listener_dropped: AtomicU64,
+}
+
+impl Stats {
+    /// Count an ingest request refused by the IngestLimiter
+    pub fn rate_limited(&self) {
+        self.rate_limited.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count the rows of the listeners dropped because the pipeline was full
+    pub fn listener_dropped(&self, count: usize) {
+        self.listener_dropped
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    /// Render the counters in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "# HELP speculare_ingest_rate_limited_total Ingest requests refused because of the sync_interval.\n\
+            # TYPE speculare_ingest_rate_limited_total counter\n\
+            speculare_ingest_rate_limited_total {}",
+            self.rate_limited.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "# HELP speculare_listener_dropped_rows_total Rows from StatsD/Graphite dropped because the pipeline was full.\n\
+            # TYPE speculare_listener_dropped_rows_total counter\n\
+            speculare_listener_dropped_rows_total {}",
+            self.listener_dropped.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "# HELP speculare_ingest_queued_rows Rows waiting to be written.\n\
+            # TYPE speculare_ingest_queued_rows gauge\n\
+            speculare_ingest_queued_rows {}",
+            PIPELINE.len()
+        );
+
+        out
+    }
+}
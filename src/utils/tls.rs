@@ -0,0 +1,91 @@
+//! Mutual TLS: the agents can present a certificate signed by the
+//! client_ca, identifying the host(s) it was issued for. Presenting one
+//! is optional, agents without certificate still use the SPTK header.
+//!
+//! The hosts are only read from the "urn:speculare:host:<uuid>" URI SANs
+//! of the certificate (as parsed by webpki), the subject and the DNS SANs
+//! are ignored.
+use actix_tls::accept::rustls_0_23::TlsStream;
+use actix_web::dev::Extensions;
+use actix_web::rt::net::TcpStream;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::any::Any;
+use std::sync::Arc;
+use webpki::EndEntityCert;
+
+/// Prefix of the URI SAN carrying the host uuid
+const URI_PREFIX: &str = "urn:speculare:host:";
+
+/// Hosts the (already verified) certificate of the peer was issued for
+#[derive(Debug, Clone)]
+pub struct PeerIdentity {
+    pub hosts: Vec<String>,
+}
+
+impl PeerIdentity {
+    fn from_cert(cert: &EndEntityCert) -> Self {
+        let hosts = cert
+            .valid_uri_names()
+            .filter_map(|uri| uri.strip_prefix(URI_PREFIX))
+            .filter(|uuid| uuid::Uuid::parse_str(uuid).is_ok())
+            .map(str::to_owned)
+            .collect();
+
+        Self { hosts }
+    }
+}
+
+/// Build the ServerConfig asking the clients for a certificate signed
+/// by the client_ca (without requiring it).
+pub fn mtls_config(
+    key_priv: &str,
+    key_cert: &str,
+    client_ca: &str,
+) -> Result<ServerConfig, String> {
+    let certs = CertificateDer::pem_file_iter(key_cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| format!("cannot read key_cert: {}", err))?;
+    let key = PrivateKeyDer::from_pem_file(key_priv)
+        .map_err(|err| format!("cannot read key_priv: {}", err))?;
+
+    let mut roots = RootCertStore::empty();
+    for ca in CertificateDer::pem_file_iter(client_ca)
+        .map_err(|err| format!("cannot read client_ca: {}", err))?
+    {
+        let ca = ca.map_err(|err| format!("cannot read client_ca: {}", err))?;
+        roots
+            .add(ca)
+            .map_err(|err| format!("invalid client_ca: {}", err))?;
+    }
+
+    let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+        .allow_unauthenticated()
+        .build()
+        .map_err(|err| format!("invalid client_ca: {}", err))?;
+
+    ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(certs, key)
+        .map_err(|err| format!("invalid key_priv/key_cert: {}", err))
+}
+
+/// Store the PeerIdentity of the connection (if the client
+/// presented a certificate), see HttpServer::on_connect.
+pub fn on_connect(conn: &dyn Any, ext: &mut Extensions) {
+    let Some(tls) = conn.downcast_ref::<TlsStream<TcpStream>>() else {
+        return;
+    };
+    let Some(cert) = tls.get_ref().1.peer_certificates().and_then(|c| c.first()) else {
+        return;
+    };
+
+    match EndEntityCert::try_from(cert) {
+        Ok(cert) => {
+            ext.insert(PeerIdentity::from_cert(&cert));
+        }
+        Err(err) => debug!("on_connect: cannot parse the peer certificate: {:?}", err),
+    }
+}